@@ -1,14 +1,12 @@
 ///! A sample VapourSynth plugin.
 #[macro_use]
-extern crate failure;
+extern crate anyhow;
 extern crate rand;
 #[macro_use]
 extern crate vapoursynth;
 
-use std::ffi::CStr;
 use std::ptr;
 
-use failure::{Error, ResultExt};
 use rand::Rng;
 use vapoursynth::core::CoreRef;
 use vapoursynth::format::FormatID;
@@ -51,7 +49,7 @@ impl<'core> Filter<'core> for Passthrough<'core> {
     ) -> Result<FrameRef<'core>, Error> {
         self.source
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Couldn't get the source frame"))
+            .ok_or_else(|| "Couldn't get the source frame".into())
     }
 }
 
@@ -98,10 +96,11 @@ impl<'core> Filter<'core> for Invert<'core> {
         let frame = self
             .source
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Couldn't get the source frame"))?;
+            .ok_or("Couldn't get the source frame")?;
 
+        // Constant formats are checked by the format constraint, but the format can be variable.
         if frame.format().sample_type() == SampleType::Float {
-            bail!("Floating point formats are not supported");
+            return Err("Floating point formats are not supported".into());
         }
 
         let mut frame = FrameRefMut::copy_of(core, &frame);
@@ -141,6 +140,10 @@ impl<'core> Filter<'core> for Invert<'core> {
 make_filter_function! {
     InvertFunction, "Invert"
 
+    constraints {
+        clip: FormatConstraint::new().sample_types(&[SampleType::Integer]),
+    }
+
     fn create_invert<'core>(
         _api: API,
         _core: CoreRef<'core>,
@@ -247,34 +250,34 @@ make_filter_function! {
     ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
         let format_id = (format as i32).into();
         let format = core.get_format(format_id)
-            .ok_or_else(|| format_err!("No such format"))?;
+            .ok_or("No such format")?;
 
         if format.sample_type() == SampleType::Float {
-            bail!("Floating point formats are not supported");
+            return Err("Floating point formats are not supported".into());
         }
 
         if width <= 0 || width > i64::from(i32::max_value()) {
-            bail!("Invalid width");
+            return Err("Invalid width".into());
         }
         let width = width as usize;
 
         if height <= 0 || height > i64::from(i32::max_value()) {
-            bail!("Invalid height");
+            return Err("Invalid height".into());
         }
         let height = height as usize;
 
         if length <= 0 || length > i64::from(i32::max_value()) {
-            bail!("Invalid length");
+            return Err("Invalid length".into());
         }
         let length = length as usize;
 
         if fpsnum <= 0 {
-            bail!("Invalid fpsnum");
+            return Err("Invalid fpsnum".into());
         }
         let fpsnum = fpsnum as u64;
 
         if fpsden <= 0 {
-            bail!("Invalid fpsden");
+            return Err("Invalid fpsden".into());
         }
         let fpsden = fpsden as u64;
 
@@ -324,12 +327,9 @@ make_filter_function! {
     fn create_make_random_noise<'core>(
         _api: API,
         core: CoreRef<'core>,
-        name: &[u8],
+        name: &str,
     ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
-        let name = unsafe { CStr::from_ptr(name.as_ptr() as _) };
-        let name = name.to_str()
-            .context("name contains invalid UTF-8")?
-            .to_owned();
+        let name = name.to_owned();
 
         let plugin = core.get_plugin_by_id(PLUGIN_IDENTIFIER).unwrap().unwrap();
         plugin
@@ -373,13 +373,19 @@ impl<'core> Filter<'core> for ArgumentTestFilter<'core> {
     ) -> Result<FrameRef<'core>, Error> {
         self.clip
             .get_frame_filter(context, n)
-            .ok_or_else(|| format_err!("Couldn't get the source frame"))
+            .ok_or_else(|| "Couldn't get the source frame".into())
     }
 }
 
 make_filter_function! {
     ArgumentTestFilterFunction, "ArgumentTest"
 
+    constraints {
+        node: FormatConstraint::new()
+            .constant_format()
+            .color_families(&[ColorFamily::Gray]),
+    }
+
     fn create_argument_test<'core>(
         api: API,
         _core: CoreRef<'core>,
@@ -398,31 +404,40 @@ make_filter_function! {
         let mut out = OwnedMap::new(api);
         function.call(&in_, &mut out);
 
-        ensure!(int == 42, "{} != 42", int);
-        #[allow(clippy::float_cmp)]
-        {
-            ensure!(float == 1337f64, "{} != 1337", float);
-        }
-        ensure!(data == &b"asd"[..], "{:?} != {:?}", data, &b"asd"[..]);
-        ensure!(
-            node.info().num_frames == Property::Constant(1),
-            "{:?} != 1",
-            node.info().num_frames
-        );
-        ensure!(frame.width(0) == 320, "{} != 320", frame.width(0));
-        ensure!(out.get::<i64>("val").map(|x| x == 10).unwrap_or(false), "Incorrect function");
-        ensure!(optional_int.is_some(), "optional_int is missing");
-        ensure!(optional_int.unwrap() == 123, "{} != 123", optional_int.unwrap());
-        ensure!(another_optional_int.is_none(), "another_optional_int was present");
-
-        let mut frame_array = frame_array;
-        ensure!(frame_array.len() == 2, "{} != 2", frame_array.len());
-        let frame = frame_array.next().unwrap();
-        ensure!(frame.width(0) == 256, "{} != 256", frame.width(0));
-        let frame = frame_array.next().unwrap();
-        ensure!(frame.width(0) == 64, "{} != 64", frame.width(0));
-
-        ensure!(optional_frame_array.is_none(), "optional_frame_array was present");
+        // anyhow errors are converted with `?`.
+        let check = || -> anyhow::Result<()> {
+            ensure!(int == 42, "{} != 42", int);
+            #[allow(clippy::float_cmp)]
+            {
+                ensure!(float == 1337f64, "{} != 1337", float);
+            }
+            ensure!(data == &b"asd"[..], "{:?} != {:?}", data, &b"asd"[..]);
+            ensure!(
+                node.info().num_frames == Property::Constant(1),
+                "{:?} != 1",
+                node.info().num_frames
+            );
+            ensure!(frame.width(0) == 320, "{} != 320", frame.width(0));
+            ensure!(
+                out.get::<i64>("val").map(|x| x == 10).unwrap_or(false),
+                "Incorrect function"
+            );
+            ensure!(optional_int.is_some(), "optional_int is missing");
+            ensure!(optional_int.unwrap() == 123, "{} != 123", optional_int.unwrap());
+            ensure!(another_optional_int.is_none(), "another_optional_int was present");
+
+            let mut frame_array = frame_array;
+            ensure!(frame_array.len() == 2, "{} != 2", frame_array.len());
+            let frame = frame_array.next().unwrap();
+            ensure!(frame.width(0) == 256, "{} != 256", frame.width(0));
+            let frame = frame_array.next().unwrap();
+            ensure!(frame.width(0) == 64, "{} != 64", frame.width(0));
+
+            ensure!(optional_frame_array.is_none(), "optional_frame_array was present");
+
+            Ok(())
+        };
+        check()?;
 
         Ok(Some(Box::new(ArgumentTestFilter { clip: node })))
     }
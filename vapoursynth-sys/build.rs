@@ -1,13 +1,28 @@
+extern crate pkg_config;
+
 use std::env;
 use std::path::PathBuf;
 
 const LIBRARY_DIR_VARIABLE: &str = "VAPOURSYNTH_LIB_DIR";
 
+// The first VapourSynth release supporting each of the API version features, newest first.
+const API_RELEASES: &[(&str, &str, u32)] = &[
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_36", "3.6", 47),
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_35", "3.5", 38),
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_34", "3.4", 30),
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_33", "3.3", 30),
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_32", "3.2", 27),
+    ("CARGO_FEATURE_GTE_VAPOURSYNTH_API_31", "3.1", 26),
+];
+
 fn main() {
     // Make sure the build script is re-run if our env variable is changed.
     println!("cargo:rerun-if-env-changed={}", LIBRARY_DIR_VARIABLE);
 
     let windows = env::var("TARGET").unwrap().contains("windows");
+    let runtime_loading = env::var("CARGO_FEATURE_RUNTIME_LOADING").is_ok();
+    let vapoursynth_functions = env::var("CARGO_FEATURE_VAPOURSYNTH_FUNCTIONS").is_ok();
+    let vsscript_functions = env::var("CARGO_FEATURE_VSSCRIPT_FUNCTIONS").is_ok();
 
     // Get the default library dir on Windows.
     let default_library_dir = if windows {
@@ -16,23 +31,49 @@ fn main() {
         None
     };
 
-    // Library directory override or the default dir on windows.
-    if let Some(dir) = env::var(LIBRARY_DIR_VARIABLE).ok() {
+    // Library directory override, the default dir on Windows or the pkg-config dirs elsewhere.
+    if let Ok(dir) = env::var(LIBRARY_DIR_VARIABLE) {
         println!("cargo:rustc-link-search=native={}", dir);
-    } else {
-        if let Some(default_library_dir) = default_library_dir {
-            for dir in default_library_dir {
-                println!("cargo:rustc-link-search=native={}", dir);
+    } else if let Some(default_library_dir) = default_library_dir {
+        for dir in default_library_dir {
+            println!("cargo:rustc-link-search=native={}", dir);
+        }
+    } else if !windows && !runtime_loading {
+        let required = required_release();
+        let mut dirs = Vec::new();
+
+        if vapoursynth_functions {
+            dirs.extend(probe_package("vapoursynth", required));
+        }
+
+        if vsscript_functions {
+            dirs.extend(probe_package("vapoursynth-script", required));
+        }
+
+        // Keep the first occurrence of every dir to preserve the search order.
+        let mut unique_dirs = Vec::new();
+        for dir in dirs {
+            if !unique_dirs.contains(&dir) {
+                unique_dirs.push(dir);
             }
         }
+
+        for dir in unique_dirs {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+        }
+    }
+
+    // The libraries are loaded at runtime, don't link to them.
+    if runtime_loading {
+        return;
     }
 
     // Handle linking to VapourSynth libs.
-    if env::var("CARGO_FEATURE_VAPOURSYNTH_FUNCTIONS").is_ok() {
+    if vapoursynth_functions {
         println!("cargo:rustc-link-lib=vapoursynth");
     }
 
-    if env::var("CARGO_FEATURE_VSSCRIPT_FUNCTIONS").is_ok() {
+    if vsscript_functions {
         let vsscript_lib_name = if windows {
             "vsscript"
         } else {
@@ -43,6 +84,70 @@ fn main() {
     }
 }
 
+// Returns the highest enabled API version and the first VapourSynth release supporting it.
+fn required_release() -> Option<(&'static str, u32)> {
+    API_RELEASES
+        .iter()
+        .find(|&&(feature, _, _)| env::var(feature).is_ok())
+        .map(|&(_, api, release)| (api, release))
+}
+
+// Parses a VapourSynth package version such as "47", "R47" or "47.2" into the release number.
+fn parse_release(version: &str) -> Option<u32> {
+    let version = version.trim_start_matches(&['R', 'r'][..]);
+    let end = version
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(version.len());
+    version[..end].parse().ok()
+}
+
+// Looks up a package with pkg-config and returns its library dirs.
+//
+// Fails the build if the package is older than the release required by the enabled API features.
+// If the package can't be found, no dirs are returned and the default linker search path is used.
+fn probe_package(package: &str, required: Option<(&str, u32)>) -> Vec<PathBuf> {
+    let library = match pkg_config::Config::new()
+        .cargo_metadata(false)
+        .env_metadata(true)
+        .probe(package)
+    {
+        Ok(library) => library,
+        Err(error) => {
+            println!(
+                "cargo:warning=Couldn't find the {} package with pkg-config, using the default \
+                 library search path. If linking fails, set {} to the directory containing the \
+                 library or add its pkg-config file to PKG_CONFIG_PATH.",
+                package, LIBRARY_DIR_VARIABLE
+            );
+            // Cargo only shows the build script output on failure, except for warnings.
+            let error = error.to_string();
+            for line in error.lines().filter(|line| !line.trim().is_empty()) {
+                println!("cargo:warning={}", line);
+            }
+            return Vec::new();
+        }
+    };
+
+    if let Some((api, required)) = required {
+        match parse_release(&library.version) {
+            Some(release) if release < required => panic!(
+                "The {} package found with pkg-config is R{}, but VapourSynth API {} requires R{} \
+                 or newer. Install a newer VapourSynth, enable a lower `vapoursynth-api-*` \
+                 feature or set {} to the directory containing a newer library.",
+                package, release, api, required, LIBRARY_DIR_VARIABLE
+            ),
+            Some(_) => {}
+            None => println!(
+                "cargo:warning=Couldn't parse the version of the {} package ({}), skipping the \
+                 API version check.",
+                package, library.version
+            ),
+        }
+    }
+
+    library.link_paths
+}
+
 // Returns the default library dirs on Windows.
 // The default dir is where the VapourSynth installer puts the libraries.
 fn get_default_library_dir() -> Option<impl Iterator<Item = String>> {
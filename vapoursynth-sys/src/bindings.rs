@@ -274,9 +274,7 @@ pub type VSFrameDoneCallback = Option<
 >;
 pub type VSMessageHandler =
     Option<unsafe extern "system" fn(msgType: c_int, msg: *const c_char, userData: *mut c_void)>;
-#[cfg(feature = "gte-vapoursynth-api-36")]
 pub type VSMessageHandlerFree = Option<unsafe extern "system" fn(userData: *mut c_void)>;
-#[cfg(feature = "gte-vapoursynth-api-36")]
 pub type VSMessageHandlerId = c_int;
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -507,47 +505,39 @@ pub struct VSAPI {
     pub setThreadCount: unsafe extern "system" fn(threads: c_int, core: *mut VSCore) -> c_int,
     pub getPluginPath: unsafe extern "system" fn(plugin: *const VSPlugin) -> *const c_char,
 
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     pub propGetIntArray: unsafe extern "system" fn(
         map: *const VSMap,
         key: *const c_char,
         error: *mut c_int,
     ) -> *const i64,
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     pub propGetFloatArray: unsafe extern "system" fn(
         map: *const VSMap,
         key: *const c_char,
         error: *mut c_int,
     ) -> *const f64,
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     pub propSetIntArray: unsafe extern "system" fn(
         map: *mut VSMap,
         key: *const c_char,
         i: *const i64,
         size: c_int,
     ) -> c_int,
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     pub propSetFloatArray: unsafe extern "system" fn(
         map: *mut VSMap,
         key: *const c_char,
         d: *const f64,
         size: c_int,
     ) -> c_int,
-    #[cfg(feature = "gte-vapoursynth-api-34")]
     pub logMessage: unsafe extern "system" fn(msgType: c_int, msg: *const c_char),
-    #[cfg(feature = "gte-vapoursynth-api-36")]
     pub addMessageHandler: unsafe extern "system" fn(
         handler: VSMessageHandler,
         free: VSMessageHandlerFree,
         userData: *mut c_void,
     ) -> VSMessageHandlerId,
-    #[cfg(feature = "gte-vapoursynth-api-36")]
     pub removeMessageHandler: unsafe extern "system" fn(id: VSMessageHandlerId) -> c_int,
-    #[cfg(feature = "gte-vapoursynth-api-36")]
     pub getCoreInfo2: unsafe extern "system" fn(core: *mut VSCore, info: *mut VSCoreInfo),
 }
 
-#[cfg(feature = "vapoursynth-functions")]
+#[cfg(all(feature = "vapoursynth-functions", not(feature = "runtime-loading")))]
 extern "system" {
     pub fn getVapourSynthAPI(version: c_int) -> *const VSAPI;
 }
@@ -563,7 +553,7 @@ pub enum VSEvalFlags {
     efSetWorkingDir = 1,
 }
 
-#[cfg(feature = "vsscript-functions")]
+#[cfg(all(feature = "vsscript-functions", not(feature = "runtime-loading")))]
 extern "system" {
     #[cfg(feature = "gte-vsscript-api-31")]
     pub fn vsscript_getApiVersion() -> c_int;
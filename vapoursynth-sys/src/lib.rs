@@ -10,6 +10,11 @@ extern crate cfg_if;
 mod bindings;
 pub use bindings::*;
 
+#[cfg(feature = "runtime-loading")]
+mod runtime;
+#[cfg(feature = "runtime-loading")]
+pub use runtime::*;
+
 macro_rules! api_version {
     ($major:expr, $minor:expr) => {
         ($major << 16) | $minor
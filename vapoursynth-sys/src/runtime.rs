@@ -0,0 +1,371 @@
+//! Runtime loading of the VapourSynth libraries.
+//!
+//! With the `runtime-loading` feature the libraries aren't linked at build time. Instead, the
+//! functions enabled with `vapoursynth-functions` and `vsscript-functions` are resolved from the
+//! libraries loaded with `load_vapoursynth()` and `load_vsscript()`. If a function is called
+//! before its library was loaded, the library is loaded from the default location.
+//!
+//! When the library can't be loaded, `getVapourSynthAPI()`, `vsscript_getVSApi()` and
+//! `vsscript_getVSApi2()` return null and `vsscript_init()` returns 0. The other functions can
+//! only be called with objects obtained from a loaded library and panic otherwise.
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use std::os::raw::{c_char, c_int, c_void};
+
+#[allow(unused_imports)]
+use super::*;
+
+/// An error indicating that a VapourSynth library couldn't be loaded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LoadError {
+    path: PathBuf,
+    message: String,
+}
+
+impl fmt::Display for LoadError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't load {}: {}", self.path.display(), self.message)
+    }
+}
+
+impl Error for LoadError {}
+
+impl LoadError {
+    /// Returns the path of the library which couldn't be loaded.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the error message of the system loader.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+#[cfg(all(
+    unix,
+    any(feature = "vapoursynth-functions", feature = "vsscript-functions")
+))]
+mod platform {
+    extern crate libc;
+
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Returns the last `dlopen()` or `dlsym()` error.
+    unsafe fn last_error() -> String {
+        let message = libc::dlerror();
+        if message.is_null() {
+            "unknown error".to_owned()
+        } else {
+            CStr::from_ptr(message).to_string_lossy().into_owned()
+        }
+    }
+
+    /// Opens a library, returning its handle.
+    pub unsafe fn open(path: &Path) -> Result<usize, String> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(|x| x.to_string())?;
+        let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            Err(last_error())
+        } else {
+            Ok(handle as usize)
+        }
+    }
+
+    /// Returns the address of a symbol of a library.
+    pub unsafe fn symbol(handle: usize, name: &CStr) -> Result<usize, String> {
+        let address = libc::dlsym(handle as *mut _, name.as_ptr());
+        if address.is_null() {
+            Err(last_error())
+        } else {
+            Ok(address as usize)
+        }
+    }
+}
+
+#[cfg(all(
+    windows,
+    any(feature = "vapoursynth-functions", feature = "vsscript-functions")
+))]
+mod platform {
+    use std::ffi::CStr;
+    use std::io;
+    use std::os::raw::{c_char, c_void};
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    extern "system" {
+        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+    }
+
+    /// Opens a library, returning its handle.
+    pub unsafe fn open(path: &Path) -> Result<usize, String> {
+        let path = path
+            .as_os_str()
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<_>>();
+        let handle = LoadLibraryW(path.as_ptr());
+        if handle.is_null() {
+            Err(io::Error::last_os_error().to_string())
+        } else {
+            Ok(handle as usize)
+        }
+    }
+
+    /// Returns the address of a symbol of a library.
+    pub unsafe fn symbol(handle: usize, name: &CStr) -> Result<usize, String> {
+        let address = GetProcAddress(handle as *mut _, name.as_ptr());
+        if address.is_null() {
+            Err(io::Error::last_os_error().to_string())
+        } else {
+            Ok(address as usize)
+        }
+    }
+}
+
+// Returns the fallback value of a function called without a loaded library.
+#[cfg(any(feature = "vapoursynth-functions", feature = "vsscript-functions"))]
+macro_rules! or_fallback {
+    ($library:expr, $name:ident) => {
+        panic!(
+            "{} was called, but the {} library isn't loaded",
+            stringify!($name),
+            $library
+        )
+    };
+    ($library:expr, $name:ident, $fallback:expr) => {
+        $fallback
+    };
+}
+
+// Generates the function table of a library, its loading function and the functions calling
+// into the table.
+#[cfg(any(feature = "vapoursynth-functions", feature = "vsscript-functions"))]
+macro_rules! runtime_library {
+    (
+        $(#[$load_attr:meta])*
+        fn $load:ident,
+        $library:expr,
+        $default_paths:expr,
+        $table:ident;
+
+        $(
+            $(#[$attr:meta])*
+            fn $name:ident($($arg:ident: $arg_type:ty),*) $(-> $ret:ty)* $(, or $fallback:expr)*;
+        )*
+    ) => {
+        mod $table {
+            #[allow(unused_imports)]
+            use super::*;
+
+            use std::ffi::CStr;
+            use std::mem;
+            use std::path::Path;
+            use std::ptr;
+            use std::sync::atomic::{AtomicPtr, Ordering};
+
+            /// The functions resolved from the library.
+            pub struct Table {
+                $(
+                    $(#[$attr])*
+                    pub $name: unsafe extern "system" fn($($arg_type),*) $(-> $ret)*,
+                )*
+            }
+
+            /// The table of the loaded library.
+            static TABLE: AtomicPtr<Table> = AtomicPtr::new(ptr::null_mut());
+
+            /// Resolves the functions from the library at `path`.
+            unsafe fn open(path: &Path) -> Result<Table, LoadError> {
+                let error = |message| LoadError {
+                    path: path.to_owned(),
+                    message,
+                };
+
+                let handle = platform::open(path).map_err(&error)?;
+
+                Ok(Table {
+                    $(
+                        $(#[$attr])*
+                        $name: {
+                            let name = concat!(stringify!($name), "\0");
+                            let name = CStr::from_bytes_with_nul_unchecked(name.as_bytes());
+                            let address = platform::symbol(handle, name).map_err(&error)?;
+                            mem::transmute::<
+                                usize,
+                                unsafe extern "system" fn($($arg_type),*) $(-> $ret)*,
+                            >(address)
+                        },
+                    )*
+                })
+            }
+
+            /// Loads the library, trying the default paths if `path` is `None`.
+            pub fn load(path: Option<&Path>) -> Result<(), LoadError> {
+                let paths = match path {
+                    Some(path) => vec![path],
+                    None if !TABLE.load(Ordering::Acquire).is_null() => return Ok(()),
+                    None => $default_paths.iter().map(Path::new).collect(),
+                };
+
+                let mut result = Ok(());
+                for path in paths {
+                    match unsafe { open(path) } {
+                        Ok(table) => {
+                            // Keep the first loaded library. The library handles are never closed.
+                            let table = Box::into_raw(Box::new(table));
+                            if TABLE
+                                .compare_exchange(
+                                    ptr::null_mut(),
+                                    table,
+                                    Ordering::AcqRel,
+                                    Ordering::Acquire,
+                                )
+                                .is_err()
+                            {
+                                drop(unsafe { Box::from_raw(table) });
+                            }
+
+                            return Ok(());
+                        }
+                        Err(error) => result = Err(error),
+                    }
+                }
+
+                result
+            }
+
+            /// Returns the table, loading the library from the default paths if needed.
+            #[inline]
+            pub fn get() -> Option<&'static Table> {
+                let table = TABLE.load(Ordering::Acquire);
+                if !table.is_null() {
+                    return Some(unsafe { &*table });
+                }
+
+                load(None).ok()?;
+                Some(unsafe { &*TABLE.load(Ordering::Acquire) })
+            }
+        }
+
+        $(#[$load_attr])*
+        #[inline]
+        pub fn $load(path: Option<&Path>) -> Result<(), LoadError> {
+            $table::load(path)
+        }
+
+        $(
+            $(#[$attr])*
+            #[allow(clippy::missing_safety_doc)]
+            #[inline]
+            pub unsafe fn $name($($arg: $arg_type),*) $(-> $ret)* {
+                match $table::get() {
+                    Some(table) => (table.$name)($($arg),*),
+                    None => or_fallback!($library, $name $(, $fallback)*),
+                }
+            }
+        )*
+    };
+}
+
+// The default library paths, searched in the order given.
+cfg_if! {
+    if #[cfg(windows)] {
+        #[cfg(feature = "vapoursynth-functions")]
+        const VAPOURSYNTH_PATHS: &[&str] = &["VapourSynth.dll"];
+        #[cfg(feature = "vsscript-functions")]
+        const VSSCRIPT_PATHS: &[&str] = &["VSScript.dll"];
+    } else if #[cfg(target_os = "macos")] {
+        #[cfg(feature = "vapoursynth-functions")]
+        const VAPOURSYNTH_PATHS: &[&str] = &["libvapoursynth.dylib"];
+        #[cfg(feature = "vsscript-functions")]
+        const VSSCRIPT_PATHS: &[&str] =
+            &["libvapoursynth-script.0.dylib", "libvapoursynth-script.dylib"];
+    } else {
+        #[cfg(feature = "vapoursynth-functions")]
+        const VAPOURSYNTH_PATHS: &[&str] = &["libvapoursynth.so"];
+        #[cfg(feature = "vsscript-functions")]
+        const VSSCRIPT_PATHS: &[&str] =
+            &["libvapoursynth-script.so.0", "libvapoursynth-script.so"];
+    }
+}
+
+#[cfg(feature = "vapoursynth-functions")]
+runtime_library! {
+    /// Loads the VapourSynth library.
+    ///
+    /// If `path` is `None`, the library is loaded from the default system location, unless it's
+    /// already loaded. Otherwise the library at `path` is loaded, which fails if it can't be
+    /// loaded even if another copy of the library is already loaded. The functions are always
+    /// resolved from the first loaded library.
+    fn load_vapoursynth,
+    "VapourSynth",
+    VAPOURSYNTH_PATHS,
+    vapoursynth_table;
+
+    fn getVapourSynthAPI(version: c_int) -> *const VSAPI, or ::std::ptr::null();
+}
+
+#[cfg(feature = "vsscript-functions")]
+runtime_library! {
+    /// Loads the VSScript library.
+    ///
+    /// If `path` is `None`, the library is loaded from the default system location, unless it's
+    /// already loaded. Otherwise the library at `path` is loaded, which fails if it can't be
+    /// loaded even if another copy of the library is already loaded. The functions are always
+    /// resolved from the first loaded library.
+    fn load_vsscript,
+    "VSScript",
+    VSSCRIPT_PATHS,
+    vsscript_table;
+
+    #[cfg(feature = "gte-vsscript-api-31")]
+    fn vsscript_getApiVersion() -> c_int;
+    fn vsscript_init() -> c_int, or 0;
+    fn vsscript_finalize() -> c_int, or 0;
+    fn vsscript_evaluateScript(
+        handle: *mut *mut VSScript,
+        script: *const c_char,
+        scriptFilename: *const c_char,
+        flags: c_int
+    ) -> c_int;
+    fn vsscript_evaluateFile(
+        handle: *mut *mut VSScript,
+        scriptFilename: *const c_char,
+        flags: c_int
+    ) -> c_int;
+    fn vsscript_createScript(handle: *mut *mut VSScript) -> c_int;
+    fn vsscript_freeScript(handle: *mut VSScript);
+    fn vsscript_getError(handle: *mut VSScript) -> *const c_char;
+    fn vsscript_getOutput(handle: *mut VSScript, index: c_int) -> *mut VSNodeRef;
+    #[cfg(feature = "gte-vsscript-api-31")]
+    fn vsscript_getOutput2(
+        handle: *mut VSScript,
+        index: c_int,
+        alpha: *mut *mut VSNodeRef
+    ) -> *mut VSNodeRef;
+    fn vsscript_clearOutput(handle: *mut VSScript, index: c_int) -> c_int;
+    fn vsscript_getCore(handle: *mut VSScript) -> *mut VSCore;
+    fn vsscript_getVSApi() -> *const VSAPI, or ::std::ptr::null();
+    #[cfg(feature = "gte-vsscript-api-32")]
+    fn vsscript_getVSApi2(version: c_int) -> *const VSAPI, or ::std::ptr::null();
+    fn vsscript_getVariable(
+        handle: *mut VSScript,
+        name: *const c_char,
+        dst: *mut VSMap
+    ) -> c_int;
+    fn vsscript_setVariable(handle: *mut VSScript, vars: *const VSMap) -> c_int;
+    fn vsscript_clearVariable(handle: *mut VSScript, name: *const c_char) -> c_int;
+    fn vsscript_clearEnvironment(handle: *mut VSScript);
+}
@@ -2,9 +2,9 @@
 // The main difference is what the errors look like.
 #![allow(unused)]
 #[macro_use]
-extern crate failure;
+extern crate anyhow;
 
-use failure::{err_msg, Error, ResultExt};
+use anyhow::{Context, Error};
 
 #[cfg(all(
     feature = "vsscript-functions",
@@ -361,7 +361,7 @@ mod inner {
                 if state.error.is_none() {
                     state.error = Some((
                         n,
-                        err_msg(error.into_inner().to_string_lossy().into_owned()),
+                        Error::msg(error.into_inner().to_string_lossy().into_owned()),
                     ))
                 }
             }
@@ -431,12 +431,12 @@ mod inner {
                         match writeln!(state.timecodes_file.as_mut().unwrap(), "{:.6}", timecode)
                             .context("Couldn't output the timecode")
                         {
-                            Err(error) => state.error = Some((n, error.into())),
+                            Err(error) => state.error = Some((n, error)),
                             Ok(()) => {
                                 if let Err(error) = update_timecodes(&frame, &mut state)
                                     .context("Couldn't update the timecodes")
                                 {
-                                    state.error = Some((n, error.into()));
+                                    state.error = Some((n, error));
                                 }
                             }
                         }
@@ -901,9 +901,9 @@ mod inner {
 
 fn main() {
     if let Err(err) = inner::run() {
-        eprintln!("Error: {}", err.as_fail());
+        eprintln!("Error: {}", err);
 
-        for cause in err.iter_causes() {
+        for cause in err.chain().skip(1) {
             eprintln!("Caused by: {}", cause);
         }
 
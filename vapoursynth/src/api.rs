@@ -1,9 +1,11 @@
 //! Most general VapourSynth API functions.
 
 use std::ffi::{CStr, CString, NulError};
+use std::fmt;
 use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
 use std::ptr::{self, NonNull};
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicI32, AtomicPtr, Ordering};
 use std::{mem, panic, process};
 use vapoursynth_sys as ffi;
 
@@ -19,9 +21,178 @@ pub struct API {
 unsafe impl Send for API {}
 unsafe impl Sync for API {}
 
+#[cfg(feature = "runtime-loading")]
+pub use vapoursynth_sys::LoadError;
+
+/// Loads the VapourSynth library.
+///
+/// This is only needed to load the library from a custom `path`, otherwise it's loaded from the
+/// default system location on the first call to `API::get()`. With `path` equal to `None`, this
+/// loads the library from the default location unless it's already loaded.
+///
+/// The library is only used for retrieving the API if the VSScript functions of API 3.2 aren't
+/// enabled, otherwise see `vsscript::load_library()`.
+#[cfg(all(feature = "runtime-loading", feature = "vapoursynth-functions"))]
+#[inline]
+pub fn load_library(path: Option<&Path>) -> Result<(), LoadError> {
+    ffi::load_vapoursynth(path)
+}
+
 /// A cached API pointer. Note that this is `*const ffi::VSAPI`, not `*mut`.
 static RAW_API: AtomicPtr<ffi::VSAPI> = AtomicPtr::new(ptr::null_mut());
 
+/// The API version negotiated with VapourSynth, or zero if it's not known.
+static RAW_API_VERSION: AtomicI32 = AtomicI32::new(0);
+
+/// The highest VapourSynth API version supported by the crate.
+const MAX_API_VERSION: ApiVersion = ApiVersion { major: 3, minor: 6 };
+
+/// A boxed message handler callback.
+type MessageHandlerCallback = Box<dyn FnMut(MessageType, &CStr) + Send + 'static>;
+
+/// A VapourSynth API version.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ApiVersion {
+    pub major: i32,
+    pub minor: i32,
+}
+
+impl ApiVersion {
+    /// Converts a version in the VapourSynth format, `(major << 16) | minor`.
+    #[inline]
+    pub fn from_raw(version: i32) -> Self {
+        Self {
+            major: version >> 16,
+            minor: version & 0xFFFF,
+        }
+    }
+
+    /// Converts the version into the VapourSynth format, `(major << 16) | minor`.
+    #[inline]
+    pub fn into_raw(self) -> i32 {
+        (self.major << 16) | self.minor
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// VapourSynth functionality which isn't available in all API versions.
+///
+/// Use `API::supports()` to check whether the core supports a capability.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Capability {
+    /// Retrieving and setting arrays of integers and floating point numbers in maps (API 3.1).
+    PropArrays,
+    /// Retrieving the path of a plugin with `Plugin::path()` (API 3.1).
+    PluginPath,
+    /// Sending messages with `API::log()` (API 3.4).
+    LogMessage,
+    /// Adding and removing message handlers with `API::add_message_handler()` and
+    /// `API::remove_message_handler()` (API 3.6).
+    AddMessageHandler,
+    /// Changing the core settings with `CoreRef::set_max_cache_size()` and
+    /// `CoreRef::set_thread_count()`, which is only thread-safe starting with API 3.6.
+    CoreSettings,
+    /// Retrieving the core info without racing with changes to the core settings (API 3.6).
+    CoreInfo2,
+}
+
+impl Capability {
+    /// Returns the first API version supporting the capability.
+    #[inline]
+    pub fn required_version(self) -> ApiVersion {
+        let minor = match self {
+            Capability::PropArrays | Capability::PluginPath => 1,
+            Capability::LogMessage => 4,
+            Capability::AddMessageHandler | Capability::CoreSettings | Capability::CoreInfo2 => 6,
+        };
+
+        ApiVersion { major: 3, minor }
+    }
+}
+
+/// An error indicating that the VapourSynth core doesn't support a capability.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error(
+    "{capability:?} requires VapourSynth API {}, but only API {version} is available",
+    .capability.required_version()
+)]
+pub struct UnsupportedError {
+    capability: Capability,
+    version: ApiVersion,
+}
+
+impl UnsupportedError {
+    #[inline]
+    pub(crate) fn new(capability: Capability, version: ApiVersion) -> Self {
+        Self {
+            capability,
+            version,
+        }
+    }
+
+    /// Returns the unsupported capability.
+    #[inline]
+    pub fn capability(&self) -> Capability {
+        self.capability
+    }
+
+    /// Returns the API version of the core.
+    #[inline]
+    pub fn version(&self) -> ApiVersion {
+        self.version
+    }
+}
+
+/// The error type for `API::log()`.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum LogError {
+    #[error("Couldn't convert the message to a CString")]
+    CStringConversion(#[source] NulError),
+    #[error("Logging isn't supported by the core")]
+    Unsupported(#[source] UnsupportedError),
+}
+
+impl From<NulError> for LogError {
+    #[inline]
+    fn from(x: NulError) -> Self {
+        LogError::CStringConversion(x)
+    }
+}
+
+impl From<UnsupportedError> for LogError {
+    #[inline]
+    fn from(x: UnsupportedError) -> Self {
+        LogError::Unsupported(x)
+    }
+}
+
+/// Retrieves the API of the highest version supported by both the crate and VapourSynth, down to
+/// the version selected with features.
+///
+/// Returns the API pointer and the raw version, or `None` if no version is supported.
+fn negotiate<F>(get_api: F) -> Option<(*mut ffi::VSAPI, i32)>
+where
+    F: Fn(c_int) -> *const ffi::VSAPI,
+{
+    let min = ApiVersion::from_raw(ffi::VAPOURSYNTH_API_VERSION);
+
+    (min.minor..=MAX_API_VERSION.minor).rev().find_map(|minor| {
+        let version = ApiVersion { minor, ..min }.into_raw();
+        let handle = get_api(version) as *mut ffi::VSAPI;
+        if handle.is_null() {
+            None
+        } else {
+            Some((handle, version))
+        }
+    })
+}
+
 /// VapourSynth log message types.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum MessageType {
@@ -72,15 +243,15 @@ macro_rules! prop_set_something {
 /// [`add_message_handler`]: struct.API.html#method.add_message_handler
 /// [`add_message_handler_trivial`]: struct.API.html#method.add_message_handler_trivial
 /// [`remove_message_handler`]: struct.API.html#method.remove_message_handler
-#[cfg(feature = "gte-vapoursynth-api-36")]
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct MessageHandlerId(ffi::VSMessageHandlerId);
 
 impl API {
     /// Retrieves the VapourSynth API.
     ///
-    /// Returns `None` on error, for example if the requested API version (selected with features,
-    /// see the crate-level docs) is not supported.
+    /// The highest API version supported by both the crate and VapourSynth is used, see
+    /// `version()`. Returns `None` on error, for example if the minimum API version (selected with
+    /// features, see the crate-level docs) is not supported.
     // If we're linking to VSScript anyway, use the VSScript function.
     #[cfg(all(feature = "vsscript-functions", feature = "gte-vsscript-api-32"))]
     #[inline]
@@ -93,14 +264,15 @@ impl API {
         let handle = if handle.is_null() {
             // Attempt retrieving it otherwise.
             vsscript::maybe_initialize();
-            let handle =
-                unsafe { ffi::vsscript_getVSApi2(ffi::VAPOURSYNTH_API_VERSION) } as *mut ffi::VSAPI;
-
-            if !handle.is_null() {
-                // If we successfully retrieved the API, cache it.
-                RAW_API.store(handle, Ordering::Relaxed);
+            match negotiate(|version| unsafe { ffi::vsscript_getVSApi2(version) }) {
+                Some((handle, version)) => {
+                    // If we successfully retrieved the API, cache it.
+                    RAW_API_VERSION.store(version, Ordering::Relaxed);
+                    RAW_API.store(handle, Ordering::Relaxed);
+                    handle
+                }
+                None => ptr::null_mut(),
             }
-            handle
         } else {
             handle
         };
@@ -116,8 +288,9 @@ impl API {
 
     /// Retrieves the VapourSynth API.
     ///
-    /// Returns `None` on error, for example if the requested API version (selected with features,
-    /// see the crate-level docs) is not supported.
+    /// The highest API version supported by both the crate and VapourSynth is used, see
+    /// `version()`. Returns `None` on error, for example if the minimum API version (selected with
+    /// features, see the crate-level docs) is not supported.
     #[cfg(all(
         feature = "vapoursynth-functions",
         not(all(feature = "vsscript-functions", feature = "gte-vsscript-api-32"))
@@ -129,14 +302,15 @@ impl API {
 
         let handle = if handle.is_null() {
             // Attempt retrieving it otherwise.
-            let handle =
-                unsafe { ffi::getVapourSynthAPI(ffi::VAPOURSYNTH_API_VERSION) } as *mut ffi::VSAPI;
-
-            if !handle.is_null() {
-                // If we successfully retrieved the API, cache it.
-                RAW_API.store(handle, Ordering::Relaxed);
+            match negotiate(|version| unsafe { ffi::getVapourSynthAPI(version) }) {
+                Some((handle, version)) => {
+                    // If we successfully retrieved the API, cache it.
+                    RAW_API_VERSION.store(version, Ordering::Relaxed);
+                    RAW_API.store(handle, Ordering::Relaxed);
+                    handle
+                }
+                None => ptr::null_mut(),
             }
-            handle
         } else {
             handle
         };
@@ -150,6 +324,25 @@ impl API {
         }
     }
 
+    /// Retrieves the VapourSynth API if possible, otherwise returns the cached API, if any.
+    ///
+    /// This works both in applications and in plugins, where the API is cached by the FFI
+    /// callbacks.
+    #[inline]
+    pub(crate) fn get_or_cached() -> Option<Self> {
+        #[cfg(any(
+            feature = "vapoursynth-functions",
+            all(feature = "vsscript-functions", feature = "gte-vsscript-api-32")
+        ))]
+        {
+            if let Some(api) = API::get() {
+                return Some(api);
+            }
+        }
+
+        NonNull::new(RAW_API.load(Ordering::Relaxed)).map(|handle| Self { handle })
+    }
+
     /// Returns the cached API.
     ///
     /// # Safety
@@ -167,13 +360,46 @@ impl API {
     /// The given pointer should be valid.
     #[inline]
     pub(crate) unsafe fn set(handle: *const ffi::VSAPI) {
+        // VapourSynth hands plugins the API of the version they requested.
+        RAW_API_VERSION.store(ffi::VAPOURSYNTH_API_VERSION, Ordering::Relaxed);
         RAW_API.store(handle as *mut _, Ordering::Relaxed);
     }
 
+    /// Returns the API version in use.
+    ///
+    /// In applications this is the highest version supported by both the crate and VapourSynth.
+    /// In plugins this is the version selected with features, since VapourSynth refuses to load
+    /// plugins requesting an unsupported version.
+    #[inline]
+    pub fn version(self) -> ApiVersion {
+        let version = RAW_API_VERSION
+            .load(Ordering::Relaxed)
+            .max(ffi::VAPOURSYNTH_API_VERSION);
+        ApiVersion::from_raw(version)
+    }
+
+    /// Returns `true` if the API version in use supports `capability`.
+    #[inline]
+    pub fn supports(self, capability: Capability) -> bool {
+        self.version() >= capability.required_version()
+    }
+
+    /// Returns an error if the API version in use doesn't support `capability`.
+    #[inline]
+    pub(crate) fn require(self, capability: Capability) -> Result<(), UnsupportedError> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(UnsupportedError::new(capability, self.version()))
+        }
+    }
+
     /// Sends a message through VapourSynth’s logging framework.
-    #[cfg(feature = "gte-vapoursynth-api-34")]
+    ///
+    /// Requires `Capability::LogMessage`.
     #[inline]
-    pub fn log(self, message_type: MessageType, message: &str) -> Result<(), NulError> {
+    pub fn log(self, message_type: MessageType, message: &str) -> Result<(), LogError> {
+        self.require(Capability::LogMessage)?;
         let message = CString::new(message)?;
         unsafe {
             (self.handle.as_ref().logMessage)(message_type.ffi_type(), message.as_ptr());
@@ -201,7 +427,7 @@ impl API {
         F: FnMut(MessageType, &CStr) + Send + 'static,
     {
         struct CallbackData {
-            callback: Box<dyn FnMut(MessageType, &CStr) + Send + 'static>,
+            callback: MessageHandlerCallback,
         }
 
         unsafe extern "system" fn c_callback(
@@ -252,15 +478,18 @@ impl API {
     /// The callback arguments are the message type and the message itself. If the callback panics,
     /// the process is aborted.
     ///
+    /// Requires `Capability::AddMessageHandler`.
+    ///
     /// [`remove_message_handler`]: #method.remove_message_handler
     #[inline]
-    #[cfg(feature = "gte-vapoursynth-api-36")]
-    pub fn add_message_handler<F>(self, callback: F) -> MessageHandlerId
+    pub fn add_message_handler<F>(self, callback: F) -> Result<MessageHandlerId, UnsupportedError>
     where
         F: FnMut(MessageType, &CStr) + Send + 'static,
     {
+        self.require(Capability::AddMessageHandler)?;
+
         struct CallbackData {
-            callback: Box<dyn FnMut(MessageType, &CStr) + Send + 'static>,
+            callback: MessageHandlerCallback,
         }
 
         unsafe extern "system" fn c_callback(
@@ -303,7 +532,7 @@ impl API {
                 Box::into_raw(user_data) as *mut c_void,
             )
         };
-        MessageHandlerId(id)
+        Ok(MessageHandlerId(id))
     }
 
     /// Installs a custom handler for the various error messages VapourSynth emits. The message
@@ -364,10 +593,16 @@ impl API {
     /// This version does not allocate at the cost of accepting a function pointer rather than an
     /// arbitrary closure. It can, however, be used with simple closures.
     ///
+    /// Requires `Capability::AddMessageHandler`.
+    ///
     /// [`remove_message_handler`]: #method.remove_message_handler
     #[inline]
-    #[cfg(feature = "gte-vapoursynth-api-36")]
-    pub fn add_message_handler_trivial(self, callback: fn(MessageType, &CStr)) -> MessageHandlerId {
+    pub fn add_message_handler_trivial(
+        self,
+        callback: fn(MessageType, &CStr),
+    ) -> Result<MessageHandlerId, UnsupportedError> {
+        self.require(Capability::AddMessageHandler)?;
+
         unsafe extern "system" fn c_callback(
             msg_type: c_int,
             msg: *const c_char,
@@ -395,7 +630,7 @@ impl API {
                 callback as *mut c_void,
             )
         };
-        MessageHandlerId(id)
+        Ok(MessageHandlerId(id))
     }
 
     /// Clears any custom message handler, restoring the default one.
@@ -415,7 +650,6 @@ impl API {
     ///
     /// If this is the only custom message handler, this will restore the default one.
     #[inline]
-    #[cfg(feature = "gte-vapoursynth-api-36")]
     pub fn remove_message_handler(self, handler_id: MessageHandlerId) {
         unsafe {
             (self.handle.as_ref().removeMessageHandler)(handler_id.0);
@@ -459,7 +693,7 @@ impl API {
     /// The caller must ensure `node` is valid.
     ///
     /// # Panics
-    /// Panics if `err_msg` is larger than `i32::max_value()`.
+    /// Panics if `err_msg` is larger than `i32::MAX`.
     #[inline]
     pub(crate) unsafe fn get_frame(
         self,
@@ -468,7 +702,7 @@ impl API {
         err_msg: &mut [c_char],
     ) -> *const ffi::VSFrameRef {
         let len = err_msg.len();
-        assert!(len <= i32::max_value() as usize);
+        assert!(len <= i32::MAX as usize);
         let len = len as i32;
 
         (self.handle.as_ref().getFrame)(n, node, err_msg.as_mut_ptr(), len)
@@ -708,8 +942,8 @@ impl API {
     /// Retrieves an array of integers from a map.
     ///
     /// # Safety
-    /// The caller must ensure `map` and `key` are valid.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// The caller must ensure `map` and `key` are valid and `Capability::PropArrays` is
+    /// supported.
     #[inline]
     pub(crate) unsafe fn prop_get_int_array(
         self,
@@ -723,8 +957,8 @@ impl API {
     /// Retrieves an array of floating point numbers from a map.
     ///
     /// # Safety
-    /// The caller must ensure `map` and `key` are valid.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// The caller must ensure `map` and `key` are valid and `Capability::PropArrays` is
+    /// supported.
     #[inline]
     pub(crate) unsafe fn prop_get_float_array(
         self,
@@ -751,7 +985,7 @@ impl API {
         append: ffi::VSPropAppendMode,
     ) -> i32 {
         let length = value.len();
-        assert!(length <= i32::max_value() as usize);
+        assert!(length <= i32::MAX as usize);
         let length = length as i32;
 
         (self.handle.as_ref().propSetData)(map, key, value.as_ptr() as _, length, append as i32)
@@ -760,11 +994,11 @@ impl API {
     /// Adds an array of integers to the map.
     ///
     /// # Safety
-    /// The caller must ensure `map` and `key` are valid.
+    /// The caller must ensure `map` and `key` are valid and `Capability::PropArrays` is
+    /// supported.
     ///
     /// # Panics
     /// Panics if `value.len()` can't fit in an `i32`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     #[inline]
     pub(crate) unsafe fn prop_set_int_array(
         self,
@@ -773,7 +1007,7 @@ impl API {
         value: &[i64],
     ) -> i32 {
         let length = value.len();
-        assert!(length <= i32::max_value() as usize);
+        assert!(length <= i32::MAX as usize);
         let length = length as i32;
 
         (self.handle.as_ref().propSetIntArray)(map, key, value.as_ptr(), length)
@@ -782,11 +1016,11 @@ impl API {
     /// Adds an array of floating point numbers to the map.
     ///
     /// # Safety
-    /// The caller must ensure `map` and `key` are valid.
+    /// The caller must ensure `map` and `key` are valid and `Capability::PropArrays` is
+    /// supported.
     ///
     /// # Panics
     /// Panics if `value.len()` can't fit in an `i32`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     #[inline]
     pub(crate) unsafe fn prop_set_float_array(
         self,
@@ -795,7 +1029,7 @@ impl API {
         value: &[f64],
     ) -> i32 {
         let length = value.len();
-        assert!(length <= i32::max_value() as usize);
+        assert!(length <= i32::MAX as usize);
         let length = length as i32;
 
         (self.handle.as_ref().propSetFloatArray)(map, key, value.as_ptr(), length)
@@ -822,25 +1056,20 @@ impl API {
     /// Returns information about the VapourSynth core.
     ///
     /// # Safety
-    /// The caller must ensure `core` is valid.
-    #[inline]
-    #[cfg(not(feature = "gte-vapoursynth-api-36"))]
-    pub(crate) unsafe fn get_core_info(self, core: *mut ffi::VSCore) -> *const ffi::VSCoreInfo {
-        (self.handle.as_ref().getCoreInfo)(core)
-    }
-
-    /// Returns information about the VapourSynth core.
-    ///
-    /// # Safety
-    /// The caller must ensure `core` is valid.
+    /// The caller must ensure `core` is valid. If `Capability::CoreInfo2` isn't supported, the
+    /// caller must ensure there are no concurrent changes to the core settings.
     #[inline]
-    #[cfg(feature = "gte-vapoursynth-api-36")]
     pub(crate) unsafe fn get_core_info(self, core: *mut ffi::VSCore) -> ffi::VSCoreInfo {
         use std::mem::MaybeUninit;
 
-        let mut core_info = MaybeUninit::uninit();
-        (self.handle.as_ref().getCoreInfo2)(core, core_info.as_mut_ptr());
-        core_info.assume_init()
+        if self.supports(Capability::CoreInfo2) {
+            let mut core_info = MaybeUninit::uninit();
+            (self.handle.as_ref().getCoreInfo2)(core, core_info.as_mut_ptr());
+            core_info.assume_init()
+        } else {
+            #[allow(deprecated)]
+            *(self.handle.as_ref().getCoreInfo)(core)
+        }
     }
 
     /// Returns a VSFormat structure from a video format identifier.
@@ -923,7 +1152,7 @@ impl API {
     #[inline]
     pub(crate) unsafe fn set_video_info(self, vi: &[ffi::VSVideoInfo], node: *mut ffi::VSNode) {
         let length = vi.len();
-        assert!(length <= i32::max_value() as usize);
+        assert!(length <= i32::MAX as usize);
         let length = length as i32;
 
         (self.handle.as_ref().setVideoInfo)(vi.as_ptr(), length, node);
@@ -1064,10 +1293,9 @@ impl API {
     /// VapourSynth retains ownership of the returned pointer.
     ///
     /// # Safety
-    /// The caller must ensure all pointers are valid.
+    /// The caller must ensure all pointers are valid and `Capability::PluginPath` is supported.
     // This was introduced in R25 without bumping the API version (R3) but we must be sure it's
     // there, so require R3.1.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     #[inline]
     pub(crate) unsafe fn get_plugin_path(self, plugin: *mut ffi::VSPlugin) -> *const c_char {
         (self.handle.as_ref().getPluginPath)(plugin)
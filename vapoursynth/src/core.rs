@@ -3,13 +3,18 @@
 use std::ffi::{CStr, CString, NulError};
 use std::fmt;
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
 use vapoursynth_sys as ffi;
 
-use api::API;
+use api::{Capability, UnsupportedError, API};
 use format::{ColorFamily, Format, FormatID, SampleType};
-use map::OwnedMap;
+use graph;
+use map::{Map, OwnedMap};
+use node::Node;
 use plugin::Plugin;
+use plugins::ffi::FilterInstance;
+use plugins::{self, Filter};
 
 /// Contains information about a VapourSynth core.
 #[derive(Debug, Clone, Copy, Hash)]
@@ -34,6 +39,18 @@ pub struct Info {
     pub used_framebuffer_size: u64,
 }
 
+/// An error that occurred while creating a filter with `CoreRef::create_filter()`.
+#[derive(Error, Debug)]
+pub enum CreateFilterError {
+    /// The filter name contains a null byte.
+    #[error("Couldn't convert the filter name to a CString")]
+    InvalidName(#[source] NulError),
+    /// VapourSynth failed to create the filter, for example because `Filter::video_info()`
+    /// panicked or `Filter::on_init()` returned an error.
+    #[error("Couldn't create the filter: {0}")]
+    Failed(String),
+}
+
 /// A reference to a VapourSynth core.
 #[derive(Debug, Clone, Copy)]
 pub struct CoreRef<'core> {
@@ -65,14 +82,6 @@ impl<'core> CoreRef<'core> {
 
     /// Returns information about the VapourSynth core.
     pub fn info(self) -> Info {
-        #[cfg(not(feature = "gte-vapoursynth-api-36"))]
-        let raw_info = unsafe {
-            API::get_cached()
-                .get_core_info(self.handle.as_ptr())
-                .as_ref()
-                .unwrap()
-        };
-        #[cfg(feature = "gte-vapoursynth-api-36")]
         let raw_info = unsafe { &API::get_cached().get_core_info(self.handle.as_ptr()) };
 
         let version_string = unsafe { CStr::from_ptr(raw_info.versionString).to_str().unwrap() };
@@ -171,11 +180,89 @@ impl<'core> CoreRef<'core> {
         unsafe { OwnedMap::from_ptr(API::get_cached().get_plugins(self.handle.as_ptr())) }
     }
 
+    /// Creates a node from a filter instance.
+    ///
+    /// This allows inserting custom processing into a filter graph without registering the filter
+    /// in a plugin. `name` is used as the filter name, for example in error messages.
+    ///
+    /// If the filter has several outputs, the node for the first one is returned. Use
+    /// `create_filter_multi()` to get all of them.
+    #[inline]
+    pub fn create_filter(
+        self,
+        name: &str,
+        filter: Box<dyn Filter<'core> + 'core>,
+    ) -> Result<Node<'core>, CreateFilterError> {
+        let mut nodes = self.create_filter_multi(name, filter)?;
+        Ok(nodes.swap_remove(0))
+    }
+
+    /// Creates nodes from a filter instance with several outputs.
+    ///
+    /// Returns the nodes of all outputs, in the order of `Filter::video_info()`. See
+    /// `create_filter()`.
+    #[inline]
+    pub fn create_filter_multi(
+        self,
+        name: &str,
+        filter: Box<dyn Filter<'core> + 'core>,
+    ) -> Result<Vec<Node<'core>>, CreateFilterError> {
+        let args = OwnedMap::new(unsafe { API::get_cached() });
+        self.create_filter_with_args(name, &args, filter)
+    }
+
+    /// Creates nodes from a filter instance, passing `args` as the filter arguments.
+    ///
+    /// The arguments are available to `Filter::on_init()` through `NodeContext` and are recorded
+    /// along with the nodes among them for `graph::export()`. Returns the nodes of all outputs,
+    /// like `create_filter_multi()`.
+    pub fn create_filter_with_args(
+        self,
+        name: &str,
+        args: &Map<'core>,
+        filter: Box<dyn Filter<'core> + 'core>,
+    ) -> Result<Vec<Node<'core>>, CreateFilterError> {
+        let name = CString::new(name).map_err(CreateFilterError::InvalidName)?;
+        let instance = Box::into_raw(Box::new(FilterInstance::new(
+            filter,
+            &name.to_string_lossy(),
+        )));
+
+        let api = unsafe { API::get_cached() };
+        let mut out = OwnedMap::new(api);
+
+        unsafe {
+            api.create_filter(
+                args.deref(),
+                out.deref_mut().deref_mut(),
+                name.as_ptr(),
+                plugins::ffi::init,
+                plugins::ffi::get_frame,
+                Some(plugins::ffi::free),
+                ffi::VSFilterMode::fmParallel,
+                ffi::VSNodeFlags(0),
+                instance as *mut _,
+                self.ptr(),
+            );
+        }
+
+        if let Some(error) = out.error() {
+            return Err(CreateFilterError::Failed(error.into_owned()));
+        }
+
+        let nodes = out.get_node_iter("clip").unwrap().collect::<Vec<_>>();
+        graph::record(instance as usize, &name.to_string_lossy(), args, &nodes);
+        Ok(nodes)
+    }
+
     /// Sets the maximum size of the framebuffer cache. Returns the new maximum size.
-    #[cfg(feature = "gte-vapoursynth-api-36")]
+    ///
+    /// Requires `Capability::CoreSettings`.
     #[inline]
-    pub fn set_max_cache_size(&self, bytes: i64) -> i64 {
-        unsafe { API::get_cached().set_max_cache_size(bytes, self.handle.as_ptr()) }
+    pub fn set_max_cache_size(&self, bytes: i64) -> Result<i64, UnsupportedError> {
+        let api = unsafe { API::get_cached() };
+        api.require(Capability::CoreSettings)?;
+        Ok(unsafe { api.set_max_cache_size(bytes, self.handle.as_ptr()) })
     }
 
     /// Sets the number of worker threads for the given core.
@@ -183,11 +270,12 @@ impl<'core> CoreRef<'core> {
     /// If the requested number of threads is zero or lower, the number of hardware threads will be
     /// detected and used.
     ///
-    /// Returns the new thread count.
-    #[cfg(feature = "gte-vapoursynth-api-36")]
+    /// Returns the new thread count. Requires `Capability::CoreSettings`.
     #[inline]
-    pub fn set_thread_count(&self, threads: i32) -> i32 {
-        unsafe { API::get_cached().set_thread_count(threads, self.handle.as_ptr()) }
+    pub fn set_thread_count(&self, threads: i32) -> Result<i32, UnsupportedError> {
+        let api = unsafe { API::get_cached() };
+        api.require(Capability::CoreSettings)?;
+        Ok(unsafe { api.set_thread_count(threads, self.handle.as_ptr()) })
     }
 }
 
@@ -6,6 +6,11 @@ use std::marker::PhantomData;
 use std::ops::Deref;
 use vapoursynth_sys as ffi;
 
+use core::CoreRef;
+use video_info::Resolution;
+
+pub use strum::{IntoEnumIterator, ParseError};
+
 /// Contains information about a video format.
 #[derive(Debug, Clone, Copy)]
 pub struct Format<'core> {
@@ -20,9 +25,16 @@ pub struct Format<'core> {
 /// The compat formats are the only packed formats in VapourSynth. Everything else is planar. They
 /// exist for compatibility with Avisynth plugins. They are not to be implemented in native
 /// VapourSynth plugins.
+///
+/// The presets can be parsed from and displayed as their VapourSynth names, such as `YUV420P10`.
+/// Parsing is case-insensitive, so the Python constant names like `GRAY8` are accepted as well.
+/// Use `IntoEnumIterator::iter()` to iterate over all presets.
 #[allow(clippy::unreadable_literal)]
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, EnumString, Display, EnumIter,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum PresetFormat {
     Gray8 = 1000010,
     Gray16 = 1000011,
@@ -78,6 +90,20 @@ pub enum SampleType {
     Float,
 }
 
+/// A builder for custom video formats.
+///
+/// The builder starts out describing an 8-bit integer format without subsampling. Once all
+/// parameters are set, `build()` registers the format with a core, returning the existing format
+/// if an identical one is already registered.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct FormatBuilder {
+    color_family: ColorFamily,
+    sample_type: SampleType,
+    bits_per_sample: u8,
+    sub_sampling_w: u8,
+    sub_sampling_h: u8,
+}
+
 /// A unique format identifier.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct FormatID(pub(crate) i32);
@@ -187,6 +213,124 @@ impl<'core> Format<'core> {
         debug_assert!(rv >= 0 && rv <= i32::from(u8::max_value()));
         rv as u8
     }
+
+    /// Returns the width of a plane of a frame with the given width, in pixels.
+    ///
+    /// The first plane is never subsampled, the other planes are subsampled according to
+    /// `sub_sampling_w()`.
+    ///
+    /// # Panics
+    /// Panics if `plane >= plane_count()`.
+    #[inline]
+    pub fn plane_width(self, plane: usize, width: usize) -> usize {
+        assert!(plane < self.plane_count());
+
+        if plane == 0 {
+            width
+        } else {
+            width >> self.sub_sampling_w()
+        }
+    }
+
+    /// Returns the height of a plane of a frame with the given height, in pixels.
+    ///
+    /// The first plane is never subsampled, the other planes are subsampled according to
+    /// `sub_sampling_h()`.
+    ///
+    /// # Panics
+    /// Panics if `plane >= plane_count()`.
+    #[inline]
+    pub fn plane_height(self, plane: usize, height: usize) -> usize {
+        assert!(plane < self.plane_count());
+
+        if plane == 0 {
+            height
+        } else {
+            height >> self.sub_sampling_h()
+        }
+    }
+
+    /// Returns the resolution of a plane of a frame with the given resolution.
+    ///
+    /// # Panics
+    /// Panics if `plane >= plane_count()`.
+    #[inline]
+    pub fn plane_resolution(self, plane: usize, resolution: Resolution) -> Resolution {
+        Resolution {
+            width: self.plane_width(plane, resolution.width),
+            height: self.plane_height(plane, resolution.height),
+        }
+    }
+
+    /// Returns a `FormatBuilder` describing this format.
+    #[inline]
+    pub fn to_builder(self) -> FormatBuilder {
+        FormatBuilder {
+            color_family: self.color_family(),
+            sample_type: self.sample_type(),
+            bits_per_sample: self.bits_per_sample(),
+            sub_sampling_w: self.sub_sampling_w(),
+            sub_sampling_h: self.sub_sampling_h(),
+        }
+    }
+}
+
+impl FormatBuilder {
+    /// Creates a new builder for a format of the given color family.
+    #[inline]
+    pub fn new(color_family: ColorFamily) -> Self {
+        Self {
+            color_family,
+            sample_type: SampleType::Integer,
+            bits_per_sample: 8,
+            sub_sampling_w: 0,
+            sub_sampling_h: 0,
+        }
+    }
+
+    /// Sets the color family.
+    #[inline]
+    pub fn color_family(mut self, color_family: ColorFamily) -> Self {
+        self.color_family = color_family;
+        self
+    }
+
+    /// Sets the sample type.
+    #[inline]
+    pub fn sample_type(mut self, sample_type: SampleType) -> Self {
+        self.sample_type = sample_type;
+        self
+    }
+
+    /// Sets the number of significant bits per sample.
+    #[inline]
+    pub fn bits_per_sample(mut self, bits_per_sample: u8) -> Self {
+        self.bits_per_sample = bits_per_sample;
+        self
+    }
+
+    /// Sets the log2 subsampling factors of the second and third planes.
+    #[inline]
+    pub fn sub_sampling(mut self, sub_sampling_w: u8, sub_sampling_h: u8) -> Self {
+        self.sub_sampling_w = sub_sampling_w;
+        self.sub_sampling_h = sub_sampling_h;
+        self
+    }
+
+    /// Registers the described format with the core and returns it.
+    ///
+    /// Returns `None` if an invalid format is described. See `CoreRef::register_format()` for the
+    /// restrictions.
+    #[inline]
+    pub fn build<'core>(self, core: CoreRef<'core>) -> Option<Format<'core>> {
+        core.register_format(
+            self.color_family,
+            self.sample_type,
+            self.bits_per_sample,
+            self.sub_sampling_w,
+            self.sub_sampling_h,
+        )
+    }
 }
 
 impl From<PresetFormat> for FormatID {
@@ -220,6 +364,13 @@ impl From<SampleType> for ffi::VSSampleType {
     }
 }
 
+impl<'core> Display for Format<'core> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
 impl Display for ColorFamily {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
@@ -10,12 +10,13 @@ use api::API;
 use component::Component;
 use core::CoreRef;
 use format::Format;
-use map::{MapRef, MapRefMut};
+use map::{Map, MapRef, MapRefMut};
+use plugins::profiling;
 use video_info::Resolution;
 
 /// An error indicating that the frame data has non-zero padding.
-#[derive(Fail, Debug, Clone, Copy, Eq, PartialEq)]
-#[fail(display = "Frame data has non-zero padding: {}", _0)]
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error("Frame data has non-zero padding: {0}")]
 pub struct NonZeroPadding(usize);
 
 /// One frame of a clip.
@@ -123,6 +124,48 @@ impl<'core> FrameRef<'core> {
             frame: Frame::from_ptr(handle),
         }
     }
+
+    /// Converts this reference into a mutable frame.
+    ///
+    /// The frame is copied and this reference is released before the copy is returned. Since the
+    /// plane data is copy-on-write, writing to the returned frame reuses the existing buffers if
+    /// this was the only reference to them, and copies them otherwise.
+    ///
+    /// Judging by the underlying implementation, it seems that any valid `core` can be used.
+    #[inline]
+    pub fn into_mut(self, core: CoreRef<'core>) -> FrameRefMut<'core> {
+        FrameRefMut::copy_of(core, &self)
+    }
+
+    /// Returns a mutable reference to the frame, making it unique first.
+    ///
+    /// This replaces the frame held by this reference with a copy, releasing the original. As with
+    /// `into_mut()`, the plane buffers are only copied on write if they are shared with another
+    /// frame reference.
+    ///
+    /// Judging by the underlying implementation, it seems that any valid `core` can be used.
+    #[inline]
+    pub fn make_mut(&mut self, core: CoreRef<'core>) -> &mut Frame<'core> {
+        let copy = FrameRefMut::copy_of(core, &self.frame);
+        self.frame = copy.frame;
+        &mut self.frame
+    }
+
+    /// Returns a copy of this frame with its properties modified by `f`.
+    ///
+    /// The plane data isn't touched, so this never copies the pixel buffers. This reference is
+    /// released before `f` is called.
+    ///
+    /// Judging by the underlying implementation, it seems that any valid `core` can be used.
+    #[inline]
+    pub fn with_props_mut<F, E>(self, core: CoreRef<'core>, f: F) -> Result<Self, E>
+    where
+        F: FnOnce(&mut Map) -> Result<(), E>,
+    {
+        let mut frame = self.into_mut(core);
+        f(&mut frame.props_mut())?;
+        Ok(frame.into())
+    }
 }
 
 impl<'core> FrameRefMut<'core> {
@@ -144,6 +187,8 @@ impl<'core> FrameRefMut<'core> {
     /// Judging by the underlying implementation, it seems that any valid `core` can be used.
     #[inline]
     pub fn copy_of(core: CoreRef, frame: &Frame<'core>) -> Self {
+        profiling::record_frame_allocation();
+
         Self {
             frame: unsafe { Frame::from_ptr(API::get_cached().copy_frame(frame, core.ptr())) },
         }
@@ -169,6 +214,8 @@ impl<'core> FrameRefMut<'core> {
         assert!(resolution.width <= i32::max_value() as usize);
         assert!(resolution.height <= i32::max_value() as usize);
 
+        profiling::record_frame_allocation();
+
         Self {
             frame: unsafe {
                 Frame::from_ptr(API::get_cached().new_video_frame(
@@ -1,5 +1,6 @@
 //! VapourSynth callable functions.
 
+use std::fmt;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::c_void;
@@ -9,7 +10,9 @@ use vapoursynth_sys as ffi;
 
 use api::API;
 use core::CoreRef;
-use map::{Map, MapRef, MapRefMut};
+use frame::FrameRef;
+use map::{self, IntoValue, Map, MapRef, MapRefMut, OwnedMap, ValueType};
+use node::Node;
 
 /// Holds a reference to a function that may be called.
 #[derive(Debug)]
@@ -120,4 +123,209 @@ impl<'core> Function<'core> {
     pub fn call(&self, in_: &Map<'core>, out: &mut Map<'core>) {
         unsafe { API::get_cached().call_func(self.handle.as_ptr(), in_.deref(), out.deref_mut()) };
     }
+
+    /// Creates a new function from a closure with typed arguments.
+    ///
+    /// The closure arguments are retrieved from the input map using the keys in `names`, in
+    /// order. A missing or mistyped argument is reported as an error without invoking the
+    /// closure. The returned value is stored in the output map under `clip` if it's a node and
+    /// under `val` otherwise; a returned error is set as the error of the output map.
+    ///
+    /// Closure arguments and return values holding nodes, frames or functions need an explicit
+    /// `'core` lifetime annotation.
+    ///
+    /// # Panics
+    /// Panics if the number of `names` doesn't match the number of closure arguments.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # extern crate vapoursynth;
+    /// # use vapoursynth::plugins::Error;
+    /// # use vapoursynth::prelude::*;
+    /// # use vapoursynth::core::CoreRef;
+    /// # use vapoursynth::function::Function;
+    /// # fn foo<'core>(api: API, core: CoreRef<'core>) {
+    /// let function = Function::from_typed(
+    ///     api,
+    ///     core,
+    ///     &["n", "clip"],
+    ///     |n: i64, clip: Node<'core>| -> Result<Node<'core>, Error> {
+    ///         // ...
+    /// #       let _ = n;
+    ///         Ok(clip)
+    ///     },
+    /// );
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn from_typed<F, Args>(api: API, core: CoreRef<'core>, names: &[&str], callback: F) -> Self
+    where
+        F: TypedFunction<'core, Args>,
+    {
+        assert_eq!(
+            names.len(),
+            F::arity(),
+            "the number of argument names doesn't match the number of closure arguments"
+        );
+
+        let names = names
+            .iter()
+            .map(|&name| name.to_owned())
+            .collect::<Vec<_>>();
+        Self::new(api, core, move |_api, _core, in_, out| {
+            callback.call_with_map(&names, in_, out)
+        })
+    }
+
+    /// Calls the function, returning the output map or the error set by the function.
+    #[inline]
+    pub fn call_typed(&self, in_: &Map<'core>) -> Result<OwnedMap<'core>, FunctionError> {
+        let mut out = OwnedMap::new(unsafe { API::get_cached() });
+        self.call(in_, &mut out);
+
+        match out.error() {
+            Some(error) => Err(FunctionError(error.into_owned())),
+            None => Ok(out),
+        }
+    }
+}
+
+/// An error returned by a function call.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("The function call failed: {0}")]
+pub struct FunctionError(String);
+
+impl FunctionError {
+    /// Returns the error message set by the function.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A type that can be used as an argument of a typed function.
+///
+/// `Option<T>` arguments are `None` when the key is missing from the input map and `Vec<T>`
+/// arguments receive all values associated with the key.
+pub trait FunctionArgument<'core>: Sized {
+    /// Retrieves the argument from the given map.
+    fn from_map(map: &Map<'core>, key: &str) -> map::Result<Self>;
+}
+
+macro_rules! impl_function_argument {
+    ($($type:ty),*) => {
+        $(
+            impl<'core> FunctionArgument<'core> for $type {
+                #[inline]
+                fn from_map(map: &Map<'core>, key: &str) -> map::Result<Self> {
+                    map.get(key)
+                }
+            }
+
+            impl<'core> FunctionArgument<'core> for Vec<$type> {
+                #[inline]
+                fn from_map(map: &Map<'core>, key: &str) -> map::Result<Self> {
+                    map.get_iter::<$type>(key).map(Iterator::collect)
+                }
+            }
+        )*
+    };
+}
+
+impl_function_argument!(
+    i64,
+    f64,
+    String,
+    Node<'core>,
+    FrameRef<'core>,
+    Function<'core>
+);
+
+impl<'core, T: FunctionArgument<'core>> FunctionArgument<'core> for Option<T> {
+    #[inline]
+    fn from_map(map: &Map<'core>, key: &str) -> map::Result<Self> {
+        match T::from_map(map, key) {
+            Ok(x) => Ok(Some(x)),
+            Err(map::Error::KeyNotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// A closure which can be turned into a function by `Function::from_typed()`.
+///
+/// This trait is implemented for closures with up to 8 `FunctionArgument`s returning
+/// `Result<R, E>`, where `R: IntoValue` and `E: Display`.
+pub trait TypedFunction<'core, Args>: Send + Sync + 'core {
+    /// Returns the number of arguments of the closure.
+    #[doc(hidden)]
+    fn arity() -> usize
+    where
+        Self: Sized;
+
+    /// Retrieves the arguments from `in_`, calls the closure and stores the result in `out`.
+    #[doc(hidden)]
+    fn call_with_map(&self, names: &[String], in_: &Map<'core>, out: &mut Map<'core>);
 }
+
+/// Sets an error on the map, escaping the NUL characters which can't be passed to VapourSynth.
+fn set_error(out: &mut Map, message: &str) {
+    out.set_error(&message.replace('\0', "\\0")).unwrap();
+}
+
+macro_rules! impl_typed_function {
+    ($($arg:ident),*) => {
+        impl<'core, F, R, E, $($arg),*> TypedFunction<'core, ($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Result<R, E> + Send + Sync + 'core,
+            R: IntoValue<'core>,
+            E: fmt::Display,
+            $($arg: FunctionArgument<'core>),*
+        {
+            #[inline]
+            fn arity() -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call_with_map(&self, names: &[String], in_: &Map<'core>, out: &mut Map<'core>) {
+                let mut names = names.iter();
+                $(
+                    let name = names.next().unwrap();
+                    let $arg = match <$arg as FunctionArgument<'core>>::from_map(in_, name) {
+                        Ok(x) => x,
+                        Err(error) => {
+                            set_error(out, &format!("Argument `{}`: {}", name, error));
+                            return;
+                        }
+                    };
+                )*
+
+                match self($($arg),*) {
+                    Ok(value) => {
+                        let key = if R::value_type() == ValueType::Node {
+                            "clip"
+                        } else {
+                            "val"
+                        };
+
+                        if let Err(error) = out.append_value(key, value) {
+                            set_error(out, &error.to_string());
+                        }
+                    }
+                    Err(error) => set_error(out, &error.to_string()),
+                }
+            }
+        }
+    };
+}
+
+impl_typed_function!();
+impl_typed_function!(T1);
+impl_typed_function!(T1, T2);
+impl_typed_function!(T1, T2, T3);
+impl_typed_function!(T1, T2, T3, T4);
+impl_typed_function!(T1, T2, T3, T4, T5);
+impl_typed_function!(T1, T2, T3, T4, T5, T6);
+impl_typed_function!(T1, T2, T3, T4, T5, T6, T7);
+impl_typed_function!(T1, T2, T3, T4, T5, T6, T7, T8);
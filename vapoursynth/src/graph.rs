@@ -0,0 +1,398 @@
+//! Export of filter graphs to Graphviz DOT and JSON.
+//!
+//! The VapourSynth API 3 has no node introspection, so the graph is built from records this crate
+//! makes when it creates a filter: the filter name, its arguments and the nodes among them. Nodes
+//! created by `CoreRef::create_filter_with_args()` and the functions built upon it, such as
+//! `CoreRef::create_filter()` and `Node::map_frames()`, and by the filter functions of plugins
+//! made with this crate are recorded. Other nodes, such as the ones created by `Plugin::invoke()`
+//! or by a script, appear in the graph as sources with an unknown filter.
+//!
+//! Recording is disabled by default, enable it with `set_enabled()` before creating the filters.
+//! Like the profiling statistics, each plugin has its own copy of the records. The record of a
+//! filter is removed when the filter is freed.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use map::{Map, ValueRef};
+use node::Node;
+use plugins::profiling::push_json_string;
+
+/// Formats of the exported graph.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Format {
+    /// Graphviz DOT, see `Graph::to_dot()`.
+    Dot,
+    /// JSON, see `Graph::to_json()`.
+    Json,
+}
+
+/// The values of a recorded filter argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Ints(Vec<i64>),
+    Floats(Vec<f64>),
+    Data(Vec<Vec<u8>>),
+    /// The nodes, as their `Node::id()`.
+    Nodes(Vec<usize>),
+    /// The number of frames.
+    Frames(usize),
+    /// The number of functions.
+    Functions(usize),
+}
+
+impl Argument {
+    /// Records the values of an argument.
+    fn new(values: ValueRef) -> Self {
+        match values {
+            ValueRef::Ints(x) => Argument::Ints(x.collect()),
+            ValueRef::Floats(x) => Argument::Floats(x.collect()),
+            ValueRef::Data(x) => Argument::Data(x.map(<[u8]>::to_vec).collect()),
+            ValueRef::Nodes(x) => Argument::Nodes(x.map(|node| node.id()).collect()),
+            ValueRef::Frames(x) => Argument::Frames(x.len()),
+            ValueRef::Functions(x) => Argument::Functions(x.len()),
+        }
+    }
+}
+
+/// A filter recorded at its creation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterRecord {
+    /// The name of the filter.
+    pub name: String,
+    /// The arguments of the filter, in order.
+    pub args: Vec<(String, Argument)>,
+    /// The number of outputs of the filter.
+    pub outputs: usize,
+}
+
+/// A node of a graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    /// The `Node::id()` of the node.
+    pub id: usize,
+    /// The filter which created the node, or `None` if it wasn't recorded.
+    pub filter: Option<Arc<FilterRecord>>,
+    /// The output of the filter this node is.
+    pub output: usize,
+}
+
+/// A filter graph.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Graph {
+    nodes: Vec<GraphNode>,
+}
+
+/// The recorded filters.
+#[derive(Debug, Default)]
+struct Records {
+    // The filter and output index of the nodes by `Node::id()`.
+    nodes: HashMap<usize, (Arc<FilterRecord>, usize)>,
+    // The node IDs of the filter instances by address, for removing them when it's freed.
+    instances: HashMap<usize, Vec<usize>>,
+}
+
+lazy_static! {
+    /// The recorded filters.
+    static ref RECORDS: Mutex<Records> = Mutex::new(Records::default());
+}
+
+/// Whether recording is enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables recording the created filters.
+///
+/// Only filters created while recording is enabled appear in the graph.
+#[inline]
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if recording is enabled.
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Exports the graph of `node` and the nodes it depends on.
+///
+/// This is a shortcut for `Graph::new()` followed by `Graph::to_dot()` or `Graph::to_json()`.
+#[inline]
+pub fn export(node: &Node, format: Format) -> String {
+    let graph = Graph::new(node);
+    match format {
+        Format::Dot => graph.to_dot(),
+        Format::Json => graph.to_json(),
+    }
+}
+
+/// Records the filter of the instance at `instance`, which created `nodes` from `args`.
+pub(crate) fn record(instance: usize, name: &str, args: &Map, nodes: &[Node]) {
+    if !is_enabled() {
+        return;
+    }
+
+    let args = args
+        .iter()
+        .map(|(key, values)| (key.to_owned(), Argument::new(values)))
+        .collect();
+    let ids = nodes.iter().map(Node::id).collect::<Vec<_>>();
+    record_ids(instance, name, args, &ids);
+}
+
+/// Records the filter of the instance at `instance`, which created the nodes with IDs `ids`.
+pub(crate) fn record_ids(
+    instance: usize,
+    name: &str,
+    args: Vec<(String, Argument)>,
+    ids: &[usize],
+) {
+    let filter = Arc::new(FilterRecord {
+        name: name.to_owned(),
+        args,
+        outputs: ids.len(),
+    });
+
+    let mut records = RECORDS.lock().unwrap();
+    for (output, &id) in ids.iter().enumerate() {
+        records.nodes.insert(id, (filter.clone(), output));
+    }
+    records.instances.insert(instance, ids.to_vec());
+}
+
+/// Removes the records of the filter instance at `instance`.
+pub(crate) fn forget(instance: usize) {
+    let mut records = RECORDS.lock().unwrap();
+    if let Some(ids) = records.instances.remove(&instance) {
+        for id in ids {
+            records.nodes.remove(&id);
+        }
+    }
+}
+
+impl Graph {
+    /// Collects the graph of `node` and the nodes it depends on.
+    #[inline]
+    pub fn new(node: &Node) -> Self {
+        Self::from_id(node.id())
+    }
+
+    /// Collects the graph of the node with ID `id` and the nodes it depends on.
+    pub(crate) fn from_id(id: usize) -> Self {
+        let records = RECORDS.lock().unwrap();
+
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::new();
+        let mut seen = HashSet::new();
+        queue.push_back(id);
+        seen.insert(id);
+        while let Some(id) = queue.pop_front() {
+            let (filter, output) = match records.nodes.get(&id) {
+                Some(&(ref filter, output)) => (Some(filter.clone()), output),
+                None => (None, 0),
+            };
+
+            if let Some(ref filter) = filter {
+                for (_, arg) in &filter.args {
+                    if let Argument::Nodes(ref ids) = *arg {
+                        for &input in ids {
+                            if seen.insert(input) {
+                                queue.push_back(input);
+                            }
+                        }
+                    }
+                }
+            }
+
+            nodes.push(GraphNode { id, filter, output });
+        }
+
+        Self { nodes }
+    }
+
+    /// Returns the nodes of the graph, starting with the exported node and followed by its
+    /// dependencies in breadth-first order.
+    #[inline]
+    pub fn nodes(&self) -> &[GraphNode] {
+        &self.nodes
+    }
+
+    /// Returns the index of the node with ID `id` in `nodes()`.
+    #[inline]
+    fn index(&self, id: usize) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    /// Returns the graph in the Graphviz DOT format.
+    ///
+    /// The graph nodes are named `n` followed by their index in `nodes()`. They are labeled with
+    /// the filter name, the output index for filters with several outputs and the arguments other
+    /// than nodes. The node arguments become edges from the input to the filter, labeled with the
+    /// argument name.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = String::new();
+            match node.filter {
+                Some(ref filter) => {
+                    label.push_str(&filter.name);
+                    if filter.outputs > 1 {
+                        write!(label, "[{}]", node.output).unwrap();
+                    }
+
+                    for (key, arg) in &filter.args {
+                        if let Argument::Nodes(_) = *arg {
+                            continue;
+                        }
+
+                        write!(label, "\n{}={}", key, format_argument(arg)).unwrap();
+                    }
+                }
+                None => label.push('?'),
+            }
+
+            writeln!(dot, "    n{} [label={}];", i, dot_string(&label)).unwrap();
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let filter = match node.filter {
+                Some(ref filter) => filter,
+                None => continue,
+            };
+
+            for (key, arg) in &filter.args {
+                if let Argument::Nodes(ref ids) = *arg {
+                    for &id in ids {
+                        let input = self.index(id).unwrap();
+                        writeln!(dot, "    n{} -> n{} [label={}];", input, i, dot_string(key))
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the graph as JSON.
+    ///
+    /// The JSON is an object with a `nodes` array, containing an object for each graph node with
+    /// its index in `nodes()` as `id`, the filter name as `filter` (`null` if unknown), the
+    /// `output` index and the `args` array. Each argument is an object with its `name`, `type`
+    /// (`int`, `float`, `data`, `node`, `frame` or `function`) and `values`. Data is given as
+    /// strings, with invalid UTF-8 replaced, nodes as their `id` and frames and functions as
+    /// `null`. Non-finite floats are given as `null` as well.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"nodes\":[");
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+
+            write!(json, "{{\"id\":{},\"filter\":", i).unwrap();
+            match node.filter {
+                Some(ref filter) => push_json_string(&mut json, &filter.name),
+                None => json.push_str("null"),
+            }
+            write!(json, ",\"output\":{},\"args\":[", node.output).unwrap();
+
+            let args = node
+                .filter
+                .as_ref()
+                .map_or(&[][..], |filter| &filter.args[..]);
+            for (j, (key, arg)) in args.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+
+                json.push_str("{\"name\":");
+                push_json_string(&mut json, key);
+
+                let (value_type, values) = match *arg {
+                    Argument::Ints(ref x) => ("int", x.iter().map(i64::to_string).collect()),
+                    Argument::Floats(ref x) => (
+                        "float",
+                        x.iter()
+                            .map(|x| {
+                                if x.is_finite() {
+                                    x.to_string()
+                                } else {
+                                    "null".to_owned()
+                                }
+                            })
+                            .collect(),
+                    ),
+                    Argument::Data(ref x) => (
+                        "data",
+                        x.iter()
+                            .map(|x| {
+                                let mut string = String::new();
+                                push_json_string(&mut string, &String::from_utf8_lossy(x));
+                                string
+                            })
+                            .collect(),
+                    ),
+                    Argument::Nodes(ref x) => (
+                        "node",
+                        x.iter()
+                            .map(|&id| self.index(id).unwrap().to_string())
+                            .collect(),
+                    ),
+                    Argument::Frames(count) => ("frame", vec!["null".to_owned(); count]),
+                    Argument::Functions(count) => ("function", vec!["null".to_owned(); count]),
+                };
+
+                write!(
+                    json,
+                    ",\"type\":\"{}\",\"values\":[{}]}}",
+                    value_type,
+                    values.join(",")
+                )
+                .unwrap();
+            }
+
+            json.push_str("]}");
+        }
+
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Formats the values of an argument for a DOT label.
+fn format_argument(arg: &Argument) -> String {
+    let values: Vec<String> = match *arg {
+        Argument::Ints(ref x) => x.iter().map(i64::to_string).collect(),
+        Argument::Floats(ref x) => x.iter().map(f64::to_string).collect(),
+        Argument::Data(ref x) => x
+            .iter()
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect(),
+        Argument::Nodes(ref x) => vec!["<node>".to_owned(); x.len()],
+        Argument::Frames(count) => vec!["<frame>".to_owned(); count],
+        Argument::Functions(count) => vec!["<function>".to_owned(); count],
+    };
+
+    values.join(", ")
+}
+
+/// Returns `x` as a DOT string literal.
+fn dot_string(x: &str) -> String {
+    let mut string = String::from("\"");
+    for c in x.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => {}
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
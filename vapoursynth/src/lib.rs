@@ -22,13 +22,12 @@
 //! ## Short example
 //!
 //! ```no_run
-//! # extern crate failure;
 //! # extern crate vapoursynth;
-//! # use failure::Error;
+//! # use std::error::Error;
 //! # #[cfg(all(feature = "vsscript-functions",
 //! #           feature = "gte-vsscript-api-31",
 //! #           any(feature = "vapoursynth-functions", feature = "gte-vsscript-api-32")))]
-//! # fn foo() -> Result<(), Error> {
+//! # fn foo() -> Result<(), Box<dyn Error>> {
 //! use vapoursynth::prelude::*;
 //!
 //! let env = Environment::from_file("test.vpy", EvalFlags::SetWorkingDir)?;
@@ -59,14 +58,11 @@
 //!
 //! ```no_run
 //! #[macro_use]
-//! extern crate failure;
-//! #[macro_use]
 //! extern crate vapoursynth;
 //!
-//! use failure::Error;
 //! use vapoursynth::prelude::*;
 //! use vapoursynth::core::CoreRef;
-//! use vapoursynth::plugins::{Filter, FilterArgument, FrameContext, Metadata};
+//! use vapoursynth::plugins::{Error, Filter, FilterArgument, FrameContext, Metadata};
 //! use vapoursynth::video_info::VideoInfo;
 //!
 //! // A simple filter that passes the frames through unchanged.
@@ -99,7 +95,7 @@
 //!     ) -> Result<FrameRef<'core>, Error> {
 //!         self.source
 //!             .get_frame_filter(context, n)
-//!             .ok_or(format_err!("Couldn't get the source frame"))
+//!             .ok_or_else(|| "Couldn't get the source frame".into())
 //!     }
 //! }
 //!
@@ -131,6 +127,9 @@
 //! Check [sample-plugin](https://github.com/YaLTeR/vapoursynth-rs/blob/master/sample-plugin) for
 //! an example plugin which exports some simple filters.
 //!
+//! Filters return `plugins::Error`, a boxed `std::error::Error`, so any error type can be
+//! returned from them with `?`, including `anyhow::Error`.
+//!
 //! ## Supported Versions
 //!
 //! All VapourSynth and VSScript API versions starting with 3.0 are supported. By default the
@@ -150,6 +149,12 @@
 //! * `vapoursynth-functions` for VapourSynth functions (`getVapourSynthAPI()`)
 //! * `vsscript-functions` for VSScript functions (`vsscript_*()`)
 //!
+//! To load the libraries at runtime instead of linking to them, additionally enable the
+//! `runtime-loading` feature. The libraries are then loaded from the default system location on
+//! first use, or from a custom path with `api::load_library()` and `vsscript::load_library()`.
+//! If a library can't be loaded, `API::get()` returns `None` and creating a VSScript environment
+//! fails with an error instead of the program failing to start.
+//!
 //! ## Building
 //!
 //! Make sure you have the corresponding libraries available if you enable the linking features.
@@ -167,16 +172,23 @@
 #![allow(unused, dead_code)]
 #![allow(clippy::trivially_copy_pass_by_ref)]
 
+#[cfg(test)]
+extern crate anyhow;
 #[macro_use]
 extern crate bitflags;
-extern crate failure;
-#[macro_use]
-extern crate failure_derive;
 #[cfg(feature = "f16-pixel-type")]
 extern crate half;
-#[cfg(any(not(feature = "gte-vsscript-api-32"), test))]
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "log")]
+extern crate log;
+extern crate strum;
+#[macro_use]
+extern crate strum_macros;
+#[macro_use]
+extern crate thiserror;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 extern crate vapoursynth_sys;
 
 #[cfg(feature = "vsscript-functions")]
@@ -188,6 +200,10 @@ pub mod core;
 pub mod format;
 pub mod frame;
 pub mod function;
+pub mod graph;
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub mod logging;
+#[macro_use]
 pub mod map;
 pub mod node;
 pub mod plugin;
@@ -198,7 +214,7 @@ pub mod prelude {
     //! The VapourSynth prelude.
     //!
     //! Contains the types you most likely want to import anyway.
-    pub use super::api::{MessageType, API};
+    pub use super::api::{Capability, MessageType, API};
     pub use super::component::Component;
     pub use super::format::{ColorFamily, PresetFormat, SampleType};
     pub use super::frame::{Frame, FrameRef, FrameRefMut};
@@ -0,0 +1,142 @@
+//! Bridges between VapourSynth messages and the `log` and `tracing` crates.
+//!
+//! `install()` forwards the messages emitted by the VapourSynth core to `tracing` if the `tracing`
+//! feature is enabled and to `log` otherwise. With the `log` feature, `Logger` does the reverse:
+//! it sends `log` records to VapourSynth's message stream, where they reach the message handlers
+//! of the host application.
+
+use std::ffi::CStr;
+
+#[cfg(feature = "log")]
+use log;
+#[cfg(feature = "tracing")]
+use tracing;
+
+use api::{Capability, MessageHandlerId, MessageType, UnsupportedError, API};
+
+/// The target of the records and events created from VapourSynth messages.
+///
+/// `Logger` ignores records with this target so that installing both bridges doesn't send the
+/// messages around in a loop.
+pub const TARGET: &str = "vapoursynth";
+
+/// Installs a VapourSynth message handler forwarding the messages to `tracing` or `log`.
+///
+/// The messages are forwarded to `tracing` if the `tracing` feature is enabled and to `log`
+/// otherwise, with the `TARGET` target. `Debug` messages are logged at the debug level, `Warning`
+/// messages at the warn level and `Critical` and `Fatal` messages at the error level.
+///
+/// Returns the ID of the message handler, which can be removed with
+/// `API::remove_message_handler()`. Requires `Capability::AddMessageHandler`.
+#[inline]
+pub fn install(api: API) -> Result<MessageHandlerId, UnsupportedError> {
+    api.add_message_handler_trivial(forward)
+}
+
+/// Forwards a VapourSynth message.
+fn forward(message_type: MessageType, message: &CStr) {
+    let message = message.to_string_lossy();
+
+    #[cfg(feature = "tracing")]
+    match message_type {
+        MessageType::Debug => tracing::debug!(target: TARGET, "{}", message),
+        MessageType::Warning => tracing::warn!(target: TARGET, "{}", message),
+        MessageType::Critical | MessageType::Fatal => {
+            tracing::error!(target: TARGET, "{}", message)
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    log::log!(target: TARGET, log_level(message_type), "{}", message);
+}
+
+/// Returns the `log` level of VapourSynth messages of the given type.
+#[cfg(feature = "log")]
+#[inline]
+pub(crate) fn log_level(message_type: MessageType) -> log::Level {
+    match message_type {
+        MessageType::Debug => log::Level::Debug,
+        MessageType::Warning => log::Level::Warn,
+        MessageType::Critical | MessageType::Fatal => log::Level::Error,
+    }
+}
+
+/// Returns the VapourSynth message type of `log` records of the given level.
+///
+/// Records are never sent as `Fatal` messages, since those abort the process.
+#[cfg(feature = "log")]
+#[inline]
+pub(crate) fn message_type(level: log::Level) -> MessageType {
+    match level {
+        log::Level::Error => MessageType::Critical,
+        log::Level::Warn => MessageType::Warning,
+        log::Level::Info | log::Level::Debug | log::Level::Trace => MessageType::Debug,
+    }
+}
+
+/// A `log` logger sending the records to VapourSynth's message stream with `API::log()`.
+///
+/// Error records are sent as `Critical` messages, warnings as `Warning` messages and the rest as
+/// `Debug` messages, prefixed with the record target. Records with the `TARGET` target are
+/// ignored.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy)]
+pub struct Logger {
+    api: API,
+    max_level: log::LevelFilter,
+}
+
+#[cfg(feature = "log")]
+impl Logger {
+    /// Creates a logger passing records of all levels.
+    ///
+    /// Requires `Capability::LogMessage`.
+    #[inline]
+    pub fn new(api: API) -> Result<Self, UnsupportedError> {
+        api.require(Capability::LogMessage)?;
+
+        Ok(Self {
+            api,
+            max_level: log::LevelFilter::Trace,
+        })
+    }
+
+    /// Sets the maximum level of the records sent to VapourSynth.
+    #[inline]
+    pub fn with_max_level(self, max_level: log::LevelFilter) -> Self {
+        Self { max_level, ..self }
+    }
+
+    /// Sets this logger as the global `log` logger.
+    ///
+    /// This also sets the maximum `log` level to the maximum level of the logger.
+    #[inline]
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(self.max_level);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "log")]
+impl log::Log for Logger {
+    #[inline]
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.max_level && metadata.target() != TARGET
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // VapourSynth messages are C strings.
+        let message = format!("{}: {}", record.target(), record.args()).replace('\0', "");
+
+        // The capability is checked on construction and the message contains no NUL bytes.
+        let _ = self.api.log(message_type(record.level()), &message);
+    }
+
+    #[inline]
+    fn flush(&self) {}
+}
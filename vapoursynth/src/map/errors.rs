@@ -1,31 +1,66 @@
 use std::ffi::NulError;
 use std::result;
+use std::str::Utf8Error;
+
+use api::UnsupportedError;
 
 /// The error type for `Map` operations.
-#[derive(Fail, Debug, Eq, PartialEq)]
+#[derive(Error, Debug, Eq, PartialEq)]
 pub enum Error {
-    #[fail(display = "The requested key wasn't found in the map")]
+    #[error("The requested key wasn't found in the map")]
     KeyNotFound,
-    #[fail(display = "The requested index was out of bounds")]
+    #[error("The requested index was out of bounds")]
     IndexOutOfBounds,
-    #[fail(display = "The given/requested value type doesn't match the type of the property")]
+    #[error("The given/requested value type doesn't match the type of the property")]
     WrongValueType,
-    #[fail(display = "The key is invalid")]
-    InvalidKey(#[cause] InvalidKeyError),
-    #[fail(display = "Couldn't convert to a CString")]
-    CStringConversion(#[cause] NulError),
+    #[error("The key is invalid")]
+    InvalidKey(#[source] InvalidKeyError),
+    #[error("Couldn't convert to a CString")]
+    CStringConversion(#[source] NulError),
+    #[error("Couldn't store the value for key `{key}`: {error}")]
+    Key { key: String, error: Box<Error> },
+    #[error("The value of key `{key}` isn't valid UTF-8")]
+    InvalidUtf8 {
+        key: String,
+        #[source]
+        error: Utf8Error,
+    },
+    #[error("Argument `{key}` out of range: {message}")]
+    ArgumentOutOfRange { key: String, message: String },
+    #[error("The operation isn't supported by the core")]
+    Unsupported(#[source] UnsupportedError),
+}
+
+impl Error {
+    /// Wraps this error into `Error::Key` with the given key.
+    #[inline]
+    pub(crate) fn with_key(self, key: &str) -> Self {
+        Error::Key {
+            key: key.to_owned(),
+            error: Box::new(self),
+        }
+    }
+
+    /// Creates an `Error::ArgumentOutOfRange` for the given argument.
+    #[inline]
+    pub(crate) fn out_of_range(key: &str, message: String) -> Self {
+        Error::ArgumentOutOfRange {
+            key: key.to_owned(),
+            message,
+        }
+    }
 }
 
 /// A specialized `Result` type for `Map` operations.
 pub type Result<T> = result::Result<T, Error>;
 
 /// An error indicating the map key is invalid.
-#[derive(Fail, Debug, Eq, PartialEq)]
+#[derive(Error, Debug, Eq, PartialEq)]
 #[rustfmt::skip]
 pub enum InvalidKeyError {
-    #[fail(display = "The key is empty")]
+    #[error("The key is empty")]
     EmptyKey,
-    #[fail(display = "The key contains an invalid character at index {}", _0)]
+    #[error("The key contains an invalid character at index {0}")]
     InvalidCharacter(usize),
 }
 
@@ -42,3 +77,10 @@ impl From<NulError> for Error {
         Error::CStringConversion(x)
     }
 }
+
+impl From<UnsupportedError> for Error {
+    #[inline]
+    fn from(x: UnsupportedError) -> Self {
+        Error::Unsupported(x)
+    }
+}
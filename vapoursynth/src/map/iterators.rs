@@ -44,6 +44,41 @@ impl<'map, 'elem> Iterator for Keys<'map, 'elem> {
 
 impl<'map, 'elem> ExactSizeIterator for Keys<'map, 'elem> {}
 
+/// An iterator over the keys of a map and their values.
+#[derive(Debug, Clone, Copy)]
+pub struct Iter<'map, 'elem: 'map> {
+    keys: Keys<'map, 'elem>,
+}
+
+impl<'map, 'elem> Iter<'map, 'elem> {
+    #[inline]
+    pub(crate) fn new(map: &'map Map<'elem>) -> Self {
+        Self {
+            keys: Keys::new(map),
+        }
+    }
+}
+
+impl<'map, 'elem> Iterator for Iter<'map, 'elem> {
+    type Item = (&'map str, ValueRef<'map, 'elem>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.keys.index;
+        let key = self.keys.next()?;
+        let raw_key = self.keys.map.key_raw(index).to_owned();
+        let values = unsafe { self.keys.map.get_values_raw_unchecked(raw_key).unwrap() };
+        Some((key, values))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<'map, 'elem> ExactSizeIterator for Iter<'map, 'elem> {}
+
 /// An iterator over the values associated with a certain key of a map.
 #[derive(Debug, Clone)]
 pub struct ValueIter<'map, 'elem: 'map, T> {
@@ -116,3 +151,62 @@ impl_value_iter!(
     Function<'elem>,
     get_function_raw_unchecked
 );
+
+macro_rules! impl_str_value_iter {
+    ($type:ty, |$x:ident| $convert:expr) => {
+        impl<'map, 'elem> ValueIter<'map, 'elem, $type> {
+            /// Creates a `ValueIter` from the given `map` and `key`, checking that all values are
+            /// valid UTF-8.
+            ///
+            /// # Safety
+            /// The caller must ensure `key` is valid.
+            #[inline]
+            pub(crate) unsafe fn new(map: &'map Map<'elem>, key: CString) -> Result<Self> {
+                let data = ValueIter::<&[u8]>::new(map, key)?;
+                for index in 0..data.count {
+                    map.get_str_raw_unchecked(&data.key, index)?;
+                }
+
+                Ok(Self {
+                    map,
+                    key: data.key,
+                    count: data.count,
+                    index: 0,
+                    _variance: PhantomData,
+                })
+            }
+        }
+
+        impl<'map, 'elem> Iterator for ValueIter<'map, 'elem, $type> {
+            type Item = $type;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.index == self.count {
+                    return None;
+                }
+
+                // The values were checked to be valid UTF-8 in new().
+                let $x = unsafe {
+                    self.map
+                        .get_str_raw_unchecked(&self.key, self.index)
+                        .unwrap()
+                };
+                self.index += 1;
+
+                Some($convert)
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = (self.count - self.index) as usize;
+                (len, Some(len))
+            }
+        }
+
+        impl<'map, 'elem> ExactSizeIterator for ValueIter<'map, 'elem, $type> {}
+    };
+}
+
+impl_str_value_iter!(&'map str, |x| x);
+impl_str_value_iter!(String, |x| x.to_owned());
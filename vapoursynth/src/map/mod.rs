@@ -2,14 +2,16 @@
 
 use std::borrow::Cow;
 use std::ffi::{CStr, CString};
+use std::fmt;
+use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::c_char;
 use std::ptr::{self, NonNull};
-use std::{mem, result, slice};
+use std::{mem, result, slice, str};
 use vapoursynth_sys as ffi;
 
-use api::API;
+use api::{Capability, API};
 use frame::{Frame, FrameRef};
 use function::Function;
 use node::Node;
@@ -18,16 +20,63 @@ mod errors;
 pub use self::errors::{Error, InvalidKeyError, Result};
 
 mod iterators;
-pub use self::iterators::{Keys, ValueIter};
+pub use self::iterators::{Iter, Keys, ValueIter};
 
 mod value;
-pub use self::value::{Value, ValueType};
+pub use self::value::{IntoValue, Value, ValueRef, ValueType};
+
+/// Creates an `OwnedMap` from key-value pairs.
+///
+/// The values can be of any type implementing `IntoValue`. The macro evaluates to a
+/// `map::Result<OwnedMap>`; if a value can't be stored, the error is `map::Error::Key` naming the
+/// offending key.
+///
+/// All values are evaluated in order, even after a value couldn't be stored. `?` and `return` in
+/// the value expressions apply to the enclosing function.
+///
+/// # Panics
+/// Panics if the VapourSynth API isn't available.
+///
+/// # Example
+/// ```no_run
+/// # #[macro_use]
+/// # extern crate vapoursynth;
+/// # use vapoursynth::prelude::*;
+/// # fn foo(node: Node) -> Result<(), vapoursynth::map::Error> {
+/// let args = map! {
+///     "clip" => node,
+///     "width" => 1920,
+///     "kernel" => "spline36",
+/// }?;
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! map {
+    ($($key:expr => $value:expr),* $(,)*) => {
+        {
+            #[allow(unused_mut)]
+            let mut map = $crate::map::OwnedMap::new_for_macro();
+            #[allow(unused_mut)]
+            let mut result: $crate::map::Result<()> = Ok(());
+            $(
+                // Evaluate the value outside of any closure so that `?` and `return` apply to the
+                // calling function.
+                let value = $value;
+                if result.is_ok() {
+                    result = map.append_value($key, value);
+                }
+            )*
+            result.map(|()| map)
+        }
+    };
+}
 
 /// A VapourSynth map.
 ///
 /// A map contains key-value pairs where the value is zero or more elements of a certain type.
 // This type is intended to be publicly used only in reference form.
-#[derive(Debug)]
 pub struct Map<'elem> {
     // The actual mutability of this depends on whether it's accessed via `&Map` or `&mut Map`.
     handle: NonNull<ffi::VSMap>,
@@ -51,7 +100,8 @@ pub struct MapRefMut<'owner, 'elem> {
 }
 
 /// An owned VapourSynth map.
-#[derive(Debug)]
+///
+/// Cloning an owned map copies all values. Nodes, frames and functions are copied by reference.
 pub struct OwnedMap<'elem> {
     // Both mutable and immutable references to this are allowed.
     map: Map<'elem>,
@@ -131,6 +181,97 @@ impl<'elem> DerefMut for OwnedMap<'elem> {
     }
 }
 
+impl<'elem> Clone for OwnedMap<'elem> {
+    fn clone(&self) -> Self {
+        let mut map = OwnedMap::new(unsafe { API::get_cached() });
+
+        if let Some(error) = self.error() {
+            map.set_error(&error).unwrap();
+        } else {
+            map.merge(self, true).unwrap();
+        }
+
+        map
+    }
+}
+
+/// Creates a map from key-value pairs.
+///
+/// # Panics
+/// Panics if a value can't be stored or if the VapourSynth API isn't available. Use
+/// `OwnedMap::try_from_iter()` to handle the errors.
+impl<'elem, K, V> FromIterator<(K, V)> for OwnedMap<'elem>
+where
+    K: AsRef<str>,
+    V: IntoValue<'elem>,
+{
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::try_from_iter(iter).unwrap()
+    }
+}
+
+/// Maps compare equal if they have the same keys with equal values, see `ValueRef`.
+///
+/// Maps holding functions never compare equal.
+impl<'elem> PartialEq for Map<'elem> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.key_count() == other.key_count()
+            && self
+                .iter()
+                .all(|(key, values)| other.get_values(key).ok() == Some(values))
+    }
+}
+
+impl<'elem> PartialEq for OwnedMap<'elem> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<'elem> fmt::Debug for Map<'elem> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'elem> fmt::Debug for OwnedMap<'elem> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.map, f)
+    }
+}
+
+/// Formats the map with one `key: values` line per key.
+impl<'elem> fmt::Display for Map<'elem> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, values) in self {
+            writeln!(f, "{}: {}", key, values)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'elem> fmt::Display for OwnedMap<'elem> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.map, f)
+    }
+}
+
+impl<'map, 'elem> IntoIterator for &'map Map<'elem> {
+    type Item = (&'map str, ValueRef<'map, 'elem>);
+    type IntoIter = Iter<'map, 'elem>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 impl<'elem> OwnedMap<'elem> {
     /// Creates a new map.
     #[inline]
@@ -140,6 +281,37 @@ impl<'elem> OwnedMap<'elem> {
         }
     }
 
+    /// Creates a new map using the API retrieved or cached by the crate.
+    ///
+    /// This function is for internal use only.
+    ///
+    /// # Panics
+    /// Panics if the VapourSynth API isn't available.
+    #[doc(hidden)]
+    #[inline]
+    pub fn new_for_macro() -> Self {
+        Self::new(API::get_or_cached().expect("The VapourSynth API isn't available"))
+    }
+
+    /// Creates a new map from key-value pairs.
+    ///
+    /// If a value can't be stored, returns `Error::Key` naming the offending key.
+    ///
+    /// # Panics
+    /// Panics if the VapourSynth API isn't available.
+    pub fn try_from_iter<I, K, V>(iter: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: IntoValue<'elem>,
+    {
+        let mut map = Self::new_for_macro();
+        for (key, value) in iter {
+            map.append_value(key.as_ref(), value)?;
+        }
+        Ok(map)
+    }
+
     /// Wraps pointer into `OwnedMap`.
     ///
     /// # Safety
@@ -419,13 +591,21 @@ impl<'elem> Map<'elem> {
 
     /// Touches the key. That is, if the key exists, nothing happens, otherwise a key is created
     /// with no values associated.
+    ///
+    /// Returns `Error::WrongValueType` if the key exists with a different value type.
     #[inline]
     pub fn touch(&mut self, key: &str, value_type: ValueType) -> Result<()> {
         let key = Map::make_raw_key(key)?;
         unsafe {
-            self.touch_raw_unchecked(&key, value_type);
+            match self.value_type_raw_unchecked(&key) {
+                Ok(x) if x != value_type => Err(Error::WrongValueType),
+                Ok(_) => Ok(()),
+                Err(_) => {
+                    self.touch_raw_unchecked(&key, value_type);
+                    Ok(())
+                }
+            }
         }
-        Ok(())
     }
 
     /// Retrieves a property value.
@@ -443,6 +623,66 @@ impl<'elem> Map<'elem> {
         T::get_iter_from_map(self, key)
     }
 
+    /// Retrieves the values associated with a key, whatever their type.
+    #[inline]
+    pub fn get_values<'map>(&'map self, key: &str) -> Result<ValueRef<'map, 'elem>> {
+        let key = Map::make_raw_key(key)?;
+        unsafe { self.get_values_raw_unchecked(key) }
+    }
+
+    /// Retrieves the values associated with a key, whatever their type.
+    ///
+    /// # Safety
+    /// The caller must ensure `key` is valid.
+    pub(crate) unsafe fn get_values_raw_unchecked<'map>(
+        &'map self,
+        key: CString,
+    ) -> Result<ValueRef<'map, 'elem>> {
+        Ok(match self.value_type_raw_unchecked(&key)? {
+            ValueType::Int => ValueRef::Ints(ValueIter::<i64>::new(self, key)?),
+            ValueType::Float => ValueRef::Floats(ValueIter::<f64>::new(self, key)?),
+            ValueType::Data => ValueRef::Data(ValueIter::<&[u8]>::new(self, key)?),
+            ValueType::Node => ValueRef::Nodes(ValueIter::<Node>::new(self, key)?),
+            ValueType::Frame => ValueRef::Frames(ValueIter::<FrameRef>::new(self, key)?),
+            ValueType::Function => ValueRef::Functions(ValueIter::<Function>::new(self, key)?),
+        })
+    }
+
+    /// Appends a value of any type implementing `IntoValue`.
+    ///
+    /// Unlike the other functions, this one returns errors wrapped in `Error::Key` naming the key.
+    #[inline]
+    pub fn append_value<V: IntoValue<'elem>>(&mut self, key: &str, value: V) -> Result<()> {
+        value
+            .append_to_map(self, key)
+            .map_err(|err| err.with_key(key))
+    }
+
+    /// Copies the keys of `other` and their values into this map.
+    ///
+    /// If a key exists in both maps, its values are replaced when `overwrite` is `true` and kept
+    /// otherwise. Nodes, frames and functions are copied by reference.
+    pub fn merge(&mut self, other: &Map<'elem>, overwrite: bool) -> Result<()> {
+        for (key, values) in other {
+            match self.value_count(key) {
+                Ok(_) if !overwrite => continue,
+                Ok(_) => self.delete_key(key)?,
+                Err(Error::KeyNotFound) => {}
+                Err(err) => return Err(err),
+            }
+
+            values.append_to_map(self, key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over all keys in a map and their values.
+    #[inline]
+    pub fn iter<'map>(&'map self) -> Iter<'map, 'elem> {
+        Iter::new(self)
+    }
+
     /// Sets a property value.
     #[inline]
     pub fn set<'map, T: Value<'map, 'elem>>(&'map mut self, key: &str, x: &T) -> Result<()> {
@@ -473,10 +713,10 @@ impl<'elem> Map<'elem> {
 
     /// Retrieves an array of integers from a map.
     ///
-    /// This is faster than iterating over a `get_int_iter()`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// This is faster than iterating over a `get_int_iter()`. Requires `Capability::PropArrays`.
     #[inline]
     pub fn get_int_array(&self, key: &str) -> Result<&[i64]> {
+        unsafe { API::get_cached() }.require(Capability::PropArrays)?;
         let key = Map::make_raw_key(key)?;
         unsafe { self.get_int_array_raw_unchecked(&key) }
     }
@@ -492,10 +732,11 @@ impl<'elem> Map<'elem> {
 
     /// Retrieves an array of floating point numbers from a map.
     ///
-    /// This is faster than iterating over a `get_float_iter()`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// This is faster than iterating over a `get_float_iter()`. Requires
+    /// `Capability::PropArrays`.
     #[inline]
     pub fn get_float_array(&self, key: &str) -> Result<&[f64]> {
+        unsafe { API::get_cached() }.require(Capability::PropArrays)?;
         let key = Map::make_raw_key(key)?;
         unsafe { self.get_float_array_raw_unchecked(&key) }
     }
@@ -526,6 +767,25 @@ impl<'elem> Map<'elem> {
         unsafe { ValueIter::<&[u8]>::new(self, key) }
     }
 
+    /// Retrieves a string from a map.
+    ///
+    /// This function retrieves the first value associated with the key. Strings are stored as
+    /// data; an error naming the key is returned if the data isn't valid UTF-8.
+    #[inline]
+    pub fn get_str(&self, key: &str) -> Result<&str> {
+        let key = Map::make_raw_key(key)?;
+        unsafe { self.get_str_raw_unchecked(&key, 0) }
+    }
+
+    /// Retrieves strings from a map.
+    ///
+    /// All values are checked to be valid UTF-8 when the iterator is created.
+    #[inline]
+    pub fn get_str_iter<'map>(&'map self, key: &str) -> Result<ValueIter<'map, 'elem, &'map str>> {
+        let key = Map::make_raw_key(key)?;
+        unsafe { ValueIter::<&str>::new(self, key) }
+    }
+
     /// Retrieves a node from a map.
     ///
     /// This function retrieves the first value associated with the key.
@@ -599,8 +859,7 @@ impl<'elem> Map<'elem> {
     /// Retrieves an array of integers from a map.
     ///
     /// # Safety
-    /// The caller must ensure `key` is valid.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// The caller must ensure `key` is valid and `Capability::PropArrays` is supported.
     #[inline]
     pub(crate) unsafe fn get_int_array_raw_unchecked(&self, key: &CStr) -> Result<&[i64]> {
         let mut error = 0;
@@ -627,8 +886,7 @@ impl<'elem> Map<'elem> {
     /// Retrieves an array of floating point numbers from a map.
     ///
     /// # Safety
-    /// The caller must ensure `key` is valid.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// The caller must ensure `key` is valid and `Capability::PropArrays` is supported.
     #[inline]
     pub(crate) unsafe fn get_float_array_raw_unchecked(&self, key: &CStr) -> Result<&[f64]> {
         let mut error = 0;
@@ -657,6 +915,19 @@ impl<'elem> Map<'elem> {
         Ok(slice::from_raw_parts(value as *const u8, length as usize))
     }
 
+    /// Retrieves a string from a map.
+    ///
+    /// # Safety
+    /// The caller must ensure `key` is valid.
+    #[inline]
+    pub(crate) unsafe fn get_str_raw_unchecked(&self, key: &CStr, index: i32) -> Result<&str> {
+        let data = self.get_data_raw_unchecked(key, index)?;
+        str::from_utf8(data).map_err(|error| Error::InvalidUtf8 {
+            key: key.to_string_lossy().into_owned(),
+            error,
+        })
+    }
+
     /// Retrieves a node from a map.
     ///
     /// # Safety
@@ -729,6 +1000,12 @@ impl<'elem> Map<'elem> {
         unsafe { self.append_data_raw_unchecked(&key, x) }
     }
 
+    /// Appends a string to a map.
+    #[inline]
+    pub fn append_str(&mut self, key: &str, x: &str) -> Result<()> {
+        self.append_data(key, x.as_bytes())
+    }
+
     /// Appends a node to a map.
     #[inline]
     pub fn append_node(&mut self, key: &str, x: &Node<'elem>) -> Result<()> {
@@ -862,10 +1139,10 @@ impl<'elem> Map<'elem> {
 
     /// Sets a property value to an integer array.
     ///
-    /// This is faster than calling `append_int()` in a loop.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// This is faster than calling `append_int()` in a loop. Requires `Capability::PropArrays`.
     #[inline]
     pub fn set_int_array(&mut self, key: &str, x: &[i64]) -> Result<()> {
+        unsafe { API::get_cached() }.require(Capability::PropArrays)?;
         let key = Map::make_raw_key(key)?;
         unsafe {
             self.set_int_array_raw_unchecked(&key, x);
@@ -885,10 +1162,11 @@ impl<'elem> Map<'elem> {
 
     /// Sets a property value to a floating point number array.
     ///
-    /// This is faster than calling `append_float()` in a loop.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    /// This is faster than calling `append_float()` in a loop. Requires
+    /// `Capability::PropArrays`.
     #[inline]
     pub fn set_float_array(&mut self, key: &str, x: &[f64]) -> Result<()> {
+        unsafe { API::get_cached() }.require(Capability::PropArrays)?;
         let key = Map::make_raw_key(key)?;
         unsafe {
             self.set_float_array_raw_unchecked(&key, x);
@@ -906,6 +1184,12 @@ impl<'elem> Map<'elem> {
         Ok(())
     }
 
+    /// Sets a property value to a string.
+    #[inline]
+    pub fn set_str(&mut self, key: &str, x: &str) -> Result<()> {
+        self.set_data(key, x.as_bytes())
+    }
+
     /// Sets a property value to a node.
     #[inline]
     pub fn set_node(&mut self, key: &str, x: &Node<'elem>) -> Result<()> {
@@ -951,11 +1235,10 @@ impl<'elem> Map<'elem> {
     /// Sets a property value to an integer array.
     ///
     /// # Safety
-    /// The caller must ensure `key` is valid.
+    /// The caller must ensure `key` is valid and `Capability::PropArrays` is supported.
     ///
     /// # Panics
     /// Panics if `x.len()` can't fit in an `i32`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     #[inline]
     pub(crate) unsafe fn set_int_array_raw_unchecked(&mut self, key: &CStr, x: &[i64]) {
         let error = API::get_cached().prop_set_int_array(self, key.as_ptr(), x);
@@ -982,11 +1265,10 @@ impl<'elem> Map<'elem> {
     /// Sets a property value to a floating point number array.
     ///
     /// # Safety
-    /// The caller must ensure `key` is valid.
+    /// The caller must ensure `key` is valid and `Capability::PropArrays` is supported.
     ///
     /// # Panics
     /// Panics if `x.len()` can't fit in an `i32`.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
     #[inline]
     pub(crate) unsafe fn set_float_array_raw_unchecked(&mut self, key: &CStr, x: &[f64]) {
         let error = API::get_cached().prop_set_float_array(self, key.as_ptr(), x);
@@ -1,3 +1,7 @@
+use std::fmt;
+use std::str;
+
+use api::API;
 use frame::FrameRef;
 use function::Function;
 use map::{Map, Result, ValueIter};
@@ -14,6 +18,154 @@ pub enum ValueType {
     Function,
 }
 
+/// The values associated with a key of a map, of any type.
+///
+/// Values compare equal if they are equal element-wise. Nodes compare equal if they refer to the
+/// same output of the same node, and frames compare equal if they share the plane data and have
+/// equal properties.
+///
+/// VapourSynth returns a new reference on every function lookup and provides no way of telling
+/// whether two references point to the same function, so non-empty function values never compare
+/// equal. As a consequence, a map holding a function is not equal to itself.
+#[derive(Clone)]
+pub enum ValueRef<'map, 'elem: 'map> {
+    Ints(ValueIter<'map, 'elem, i64>),
+    Floats(ValueIter<'map, 'elem, f64>),
+    Data(ValueIter<'map, 'elem, &'map [u8]>),
+    Nodes(ValueIter<'map, 'elem, Node<'elem>>),
+    Frames(ValueIter<'map, 'elem, FrameRef<'elem>>),
+    Functions(ValueIter<'map, 'elem, Function<'elem>>),
+}
+
+impl<'map, 'elem> ValueRef<'map, 'elem> {
+    /// Returns the type of the values.
+    #[inline]
+    pub fn value_type(&self) -> ValueType {
+        match *self {
+            ValueRef::Ints(_) => ValueType::Int,
+            ValueRef::Floats(_) => ValueType::Float,
+            ValueRef::Data(_) => ValueType::Data,
+            ValueRef::Nodes(_) => ValueType::Node,
+            ValueRef::Frames(_) => ValueType::Frame,
+            ValueRef::Functions(_) => ValueType::Function,
+        }
+    }
+
+    /// Returns the number of values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        match *self {
+            ValueRef::Ints(ref x) => x.len(),
+            ValueRef::Floats(ref x) => x.len(),
+            ValueRef::Data(ref x) => x.len(),
+            ValueRef::Nodes(ref x) => x.len(),
+            ValueRef::Frames(ref x) => x.len(),
+            ValueRef::Functions(ref x) => x.len(),
+        }
+    }
+
+    /// Returns `true` if there are no values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the values to the given key, creating the key if it doesn't exist.
+    pub(crate) fn append_to_map(self, map: &mut Map<'elem>, key: &str) -> Result<()> {
+        map.touch(key, self.value_type())?;
+
+        match self {
+            ValueRef::Ints(mut iter) => iter.try_for_each(|x| map.append_int(key, x)),
+            ValueRef::Floats(mut iter) => iter.try_for_each(|x| map.append_float(key, x)),
+            ValueRef::Data(mut iter) => iter.try_for_each(|x| map.append_data(key, x)),
+            ValueRef::Nodes(mut iter) => iter.try_for_each(|x| map.append_node(key, &x)),
+            ValueRef::Frames(mut iter) => iter.try_for_each(|x| map.append_frame(key, &x)),
+            ValueRef::Functions(mut iter) => iter.try_for_each(|x| map.append_function(key, &x)),
+        }
+    }
+}
+
+/// Returns whether two nodes refer to the same output of the same node.
+fn same_node(a: &Node, b: &Node) -> bool {
+    a.id() == b.id()
+}
+
+/// Returns whether two frames share the plane data and have equal properties.
+fn same_frame(a: &FrameRef, b: &FrameRef) -> bool {
+    a.format() == b.format()
+        && (0..a.format().plane_count()).all(|plane| a.data_ptr(plane) == b.data_ptr(plane))
+        && *a.props() == *b.props()
+}
+
+impl<'map, 'elem> PartialEq for ValueRef<'map, 'elem> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.clone(), other.clone()) {
+            (ValueRef::Ints(a), ValueRef::Ints(b)) => Iterator::eq(a, b),
+            (ValueRef::Floats(a), ValueRef::Floats(b)) => Iterator::eq(a, b),
+            (ValueRef::Data(a), ValueRef::Data(b)) => Iterator::eq(a, b),
+            (ValueRef::Nodes(a), ValueRef::Nodes(b)) => {
+                a.len() == b.len() && a.zip(b).all(|(a, b)| same_node(&a, &b))
+            }
+            (ValueRef::Frames(a), ValueRef::Frames(b)) => {
+                a.len() == b.len() && a.zip(b).all(|(a, b)| same_frame(&a, &b))
+            }
+            // Functions have no identity to compare.
+            (ValueRef::Functions(a), ValueRef::Functions(b)) => a.len() == 0 && b.len() == 0,
+            _ => false,
+        }
+    }
+}
+
+impl<'map, 'elem> fmt::Debug for ValueRef<'map, 'elem> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        match self.clone() {
+            ValueRef::Ints(iter) => list.entries(iter),
+            ValueRef::Floats(iter) => list.entries(iter),
+            ValueRef::Data(iter) => list.entries(iter),
+            ValueRef::Nodes(iter) => list.entries(iter),
+            ValueRef::Frames(iter) => list.entries(iter),
+            ValueRef::Functions(iter) => list.entries(iter),
+        };
+        list.finish()
+    }
+}
+
+/// Writes the items separated by commas.
+fn write_separated<I, F>(f: &mut fmt::Formatter, iter: I, mut write_item: F) -> fmt::Result
+where
+    I: Iterator,
+    F: FnMut(&mut fmt::Formatter, I::Item) -> fmt::Result,
+{
+    for (i, item) in iter.enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_item(f, item)?;
+    }
+    Ok(())
+}
+
+/// Formats the values in a readable form.
+///
+/// Data which is valid UTF-8 is shown as a quoted string. Nodes, frames and functions are shown as
+/// placeholders.
+impl<'map, 'elem> fmt::Display for ValueRef<'map, 'elem> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.clone() {
+            ValueRef::Ints(iter) => write_separated(f, iter, |f, x| write!(f, "{}", x)),
+            ValueRef::Floats(iter) => write_separated(f, iter, |f, x| write!(f, "{}", x)),
+            ValueRef::Data(iter) => write_separated(f, iter, |f, x| match str::from_utf8(x) {
+                Ok(x) => write!(f, "{:?}", x),
+                Err(_) => write!(f, "{:?}", x),
+            }),
+            ValueRef::Nodes(iter) => write_separated(f, iter, |f, _| write!(f, "<node>")),
+            ValueRef::Frames(iter) => write_separated(f, iter, |f, _| write!(f, "<frame>")),
+            ValueRef::Functions(iter) => write_separated(f, iter, |f, _| write!(f, "<function>")),
+        }
+    }
+}
+
 /// A trait for values which can be stored in a map.
 pub trait Value<'map, 'elem: 'map>: Sized {
     /// Retrieves the value from the map.
@@ -94,6 +246,51 @@ impl<'map, 'elem: 'map> Value<'map, 'elem> for &'map [u8] {
     }
 }
 
+impl<'map, 'elem: 'map> Value<'map, 'elem> for &'map str {
+    #[inline]
+    fn get_from_map(map: &'map Map, key: &str) -> Result<Self> {
+        map.get_str(key)
+    }
+
+    #[inline]
+    fn get_iter_from_map(map: &'map Map<'elem>, key: &str) -> Result<ValueIter<'map, 'elem, Self>> {
+        map.get_str_iter(key)
+    }
+
+    #[inline]
+    fn store_in_map(map: &'map mut Map, key: &str, x: &Self) -> Result<()> {
+        map.set_str(key, x)
+    }
+
+    #[inline]
+    fn append_to_map(map: &'map mut Map, key: &str, x: &Self) -> Result<()> {
+        map.append_str(key, x)
+    }
+}
+
+impl<'map, 'elem: 'map> Value<'map, 'elem> for String {
+    #[inline]
+    fn get_from_map(map: &'map Map, key: &str) -> Result<Self> {
+        map.get_str(key).map(ToOwned::to_owned)
+    }
+
+    #[inline]
+    fn get_iter_from_map(map: &'map Map<'elem>, key: &str) -> Result<ValueIter<'map, 'elem, Self>> {
+        let key = Map::make_raw_key(key)?;
+        unsafe { ValueIter::<String>::new(map, key) }
+    }
+
+    #[inline]
+    fn store_in_map(map: &'map mut Map, key: &str, x: &Self) -> Result<()> {
+        map.set_str(key, x)
+    }
+
+    #[inline]
+    fn append_to_map(map: &'map mut Map, key: &str, x: &Self) -> Result<()> {
+        map.append_str(key, x)
+    }
+}
+
 impl<'map, 'elem: 'map> Value<'map, 'elem> for Node<'elem> {
     #[inline]
     fn get_from_map(map: &Map<'elem>, key: &str) -> Result<Self> {
@@ -159,3 +356,104 @@ impl<'map, 'elem: 'map> Value<'map, 'elem> for Function<'elem> {
         map.append_function(key, x)
     }
 }
+
+/// A trait for values which can be added to a map by `map!` and `OwnedMap::try_from_iter()`.
+///
+/// Integers, booleans (as `0` and `1`), floating point numbers, strings and byte slices (as data),
+/// nodes, frames, functions and vectors or slices of these are supported. Vectors and slices add
+/// one value per element.
+pub trait IntoValue<'elem> {
+    /// Returns the type of the value stored in the map.
+    fn value_type() -> ValueType
+    where
+        Self: Sized;
+
+    /// Appends the value to the given key.
+    fn append_to_map(self, map: &mut Map<'elem>, key: &str) -> Result<()>;
+}
+
+macro_rules! impl_into_value {
+    ($type:ty, $value_type:expr, |$map:ident, $key:ident, $x:ident| $append:expr) => {
+        impl<'a, 'elem> IntoValue<'elem> for $type {
+            #[inline]
+            fn value_type() -> ValueType {
+                $value_type
+            }
+
+            #[inline]
+            fn append_to_map(self, $map: &mut Map<'elem>, $key: &str) -> Result<()> {
+                let $x = self;
+                $append
+            }
+        }
+    };
+}
+
+impl_into_value!(i64, ValueType::Int, |map, key, x| map.append_int(key, x));
+impl_into_value!(i32, ValueType::Int, |map, key, x| {
+    map.append_int(key, i64::from(x))
+});
+impl_into_value!(bool, ValueType::Int, |map, key, x| {
+    map.append_int(key, i64::from(x))
+});
+impl_into_value!(f64, ValueType::Float, |map, key, x| map
+    .append_float(key, x));
+impl_into_value!(f32, ValueType::Float, |map, key, x| {
+    map.append_float(key, f64::from(x))
+});
+impl_into_value!(&'a [u8], ValueType::Data, |map, key, x| map
+    .append_data(key, x));
+impl_into_value!(&'a str, ValueType::Data, |map, key, x| {
+    map.append_data(key, x.as_bytes())
+});
+impl_into_value!(String, ValueType::Data, |map, key, x| {
+    map.append_data(key, x.as_bytes())
+});
+impl_into_value!(Node<'elem>, ValueType::Node, |map, key, x| map
+    .append_node(key, &x));
+impl_into_value!(&'a Node<'elem>, ValueType::Node, |map, key, x| map
+    .append_node(key, x));
+impl_into_value!(FrameRef<'elem>, ValueType::Frame, |map, key, x| {
+    map.append_frame(key, &x)
+});
+impl_into_value!(&'a FrameRef<'elem>, ValueType::Frame, |map, key, x| {
+    map.append_frame(key, x)
+});
+impl_into_value!(Function<'elem>, ValueType::Function, |map, key, x| {
+    map.append_function(key, &x)
+});
+impl_into_value!(&'a Function<'elem>, ValueType::Function, |map, key, x| {
+    map.append_function(key, x)
+});
+
+impl<'elem, T> IntoValue<'elem> for Vec<T>
+where
+    T: IntoValue<'elem>,
+{
+    #[inline]
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+
+    fn append_to_map(self, map: &mut Map<'elem>, key: &str) -> Result<()> {
+        map.touch(key, T::value_type())?;
+        self.into_iter().try_for_each(|x| x.append_to_map(map, key))
+    }
+}
+
+impl<'elem, T> IntoValue<'elem> for &[T]
+where
+    T: IntoValue<'elem> + Clone,
+{
+    #[inline]
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+
+    fn append_to_map(self, map: &mut Map<'elem>, key: &str) -> Result<()> {
+        map.touch(key, T::value_type())?;
+        self.iter()
+            .cloned()
+            .try_for_each(|x| x.append_to_map(map, key))
+    }
+}
@@ -0,0 +1,146 @@
+//! Filters backing the closure-based `Node` adaptors.
+
+use api::API;
+use core::CoreRef;
+use frame::FrameRef;
+use node::Node;
+use plugins::{Error, Filter, FrameContext};
+use video_info::VideoInfo;
+
+/// A filter applying a closure to every frame of a clip.
+pub(crate) struct MapFrames<'core, F> {
+    pub source: Node<'core>,
+    pub callback: F,
+}
+
+impl<'core, F> Filter<'core> for MapFrames<'core, F>
+where
+    F: Fn(usize, FrameRef<'core>) -> Result<FrameRef<'core>, Error> + Send + Sync + 'core,
+{
+    #[inline]
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.source.info()]
+    }
+
+    #[inline]
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, Error> {
+        self.source.request_frame_filter(context, n);
+        Ok(None)
+    }
+
+    #[inline]
+    fn get_frame(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, Error> {
+        let frame = self
+            .source
+            .get_frame_filter(context, n)
+            .ok_or_else(|| Error::from("Couldn't get the source frame"))?;
+
+        (self.callback)(n, frame)
+    }
+}
+
+/// A filter selecting for every frame the clip to take the frame from.
+pub(crate) struct FrameEval<'core, F> {
+    pub template: Node<'core>,
+    pub callback: F,
+}
+
+impl<'core, F> Filter<'core> for FrameEval<'core, F>
+where
+    F: Fn(usize) -> Result<Node<'core>, Error> + Send + Sync + 'core,
+{
+    #[inline]
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.template.info()]
+    }
+
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, Error> {
+        let node = (self.callback)(n)?;
+        node.request_frame_filter(context, n);
+
+        // Keep the selected clip with the request for `get_frame()`.
+        context.set_frame_data(node);
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, Error> {
+        // The clip was attached as a `Node` in `get_frame_initial()`.
+        let node = unsafe { context.take_frame_data::<Node<'core>>() }
+            .ok_or_else(|| Error::from("Couldn't find the selected clip"))?;
+
+        node.get_frame_filter(context, n)
+            .ok_or_else(|| Error::from("Couldn't get the frame of the selected clip"))
+    }
+}
+
+/// A filter passing the frames of several clips to a closure.
+pub(crate) struct ModifyFrame<'core, F> {
+    pub template: Node<'core>,
+    pub clips: Vec<Node<'core>>,
+    pub callback: F,
+}
+
+impl<'core, F> Filter<'core> for ModifyFrame<'core, F>
+where
+    F: Fn(usize, &[FrameRef<'core>]) -> Result<FrameRef<'core>, Error> + Send + Sync + 'core,
+{
+    #[inline]
+    fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        vec![self.template.info()]
+    }
+
+    #[inline]
+    fn get_frame_initial(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, Error> {
+        for clip in &self.clips {
+            clip.request_frame_filter(context, n);
+        }
+        Ok(None)
+    }
+
+    fn get_frame(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, Error> {
+        let frames = self
+            .clips
+            .iter()
+            .map(|clip| clip.get_frame_filter(context, n))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::from("Couldn't get the source frames"))?;
+
+        (self.callback)(n, &frames)
+    }
+}
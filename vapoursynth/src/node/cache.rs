@@ -0,0 +1,318 @@
+//! A frame cache for interactive access to a node.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use frame::{Frame, FrameRef};
+use node::{GetFrameError, Node};
+
+/// The default number of frames requested ahead of a sequential access pattern.
+pub const DEFAULT_PREFETCH: usize = 4;
+
+/// Statistics of a `CachedNode`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct CacheStats {
+    /// The number of frames returned from the cache.
+    pub hits: u64,
+    /// The number of frames which had to be generated on request.
+    pub misses: u64,
+    /// The number of frames requested in advance.
+    pub prefetched: u64,
+    /// The number of frames removed from the cache to stay within the budget.
+    pub evicted: u64,
+    /// The number of frames currently in the cache.
+    pub frames: usize,
+    /// The size of the frames currently in the cache, in bytes.
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// Returns the fraction of requests served from the cache, or `0` if there were none.
+    #[inline]
+    pub fn hit_ratio(&self) -> f64 {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            0.
+        } else {
+            self.hits as f64 / requests as f64
+        }
+    }
+}
+
+/// A node wrapper keeping recently accessed frames in a least recently used cache.
+///
+/// VapourSynth's own cache is tuned for linear processing and may drop frames a previewer
+/// scrubbing back and forth will soon need again. `CachedNode` keeps the frames it returned up to
+/// a budget in bytes, evicting the least recently used ones first.
+///
+/// When the last accesses moved through the clip with a constant step, the next frames in that
+/// direction are requested in advance with `Node::get_frame_async()` and cached once they are
+/// ready.
+///
+/// Clones share the cache.
+#[derive(Debug, Clone)]
+pub struct CachedNode<'core> {
+    node: Node<'core>,
+    prefetch: usize,
+    shared: Arc<Shared<'core>>,
+}
+
+/// The part of a `CachedNode` shared with the prefetch callbacks.
+#[derive(Debug)]
+struct Shared<'core> {
+    budget: usize,
+    state: Mutex<State<'core>>,
+}
+
+/// A cached frame.
+#[derive(Debug)]
+struct Entry<'core> {
+    frame: FrameRef<'core>,
+    size: usize,
+    last_used: u64,
+}
+
+/// The mutable state of the cache.
+#[derive(Debug, Default)]
+pub(crate) struct State<'core> {
+    frames: HashMap<usize, Entry<'core>>,
+    // Frame numbers by the time of their last use.
+    usage: BTreeMap<u64, usize>,
+    clock: u64,
+    in_flight: HashSet<usize>,
+    // The last accessed frame and the step which led to it.
+    last: Option<(usize, Option<i64>)>,
+    stats: CacheStats,
+}
+
+impl<'core> State<'core> {
+    /// Marks frame `n` as used, returning it if it's cached.
+    fn touch(&mut self, n: usize) -> Option<FrameRef<'core>> {
+        let time = self.clock;
+        let entry = self.frames.get_mut(&n)?;
+
+        self.clock += 1;
+        self.usage.remove(&entry.last_used);
+        self.usage.insert(time, n);
+        entry.last_used = time;
+
+        Some(entry.frame.clone())
+    }
+
+    /// Caches frame `n`, evicting the least recently used frames to stay within `budget`.
+    ///
+    /// Frames larger than the budget aren't cached.
+    fn insert(&mut self, n: usize, frame: FrameRef<'core>, budget: usize) {
+        let size = frame_size(&frame);
+        if size > budget || self.frames.contains_key(&n) {
+            return;
+        }
+
+        while self.stats.bytes + size > budget {
+            // The cache can't be empty here since the frame fits into the budget.
+            let oldest = *self.usage.values().next().unwrap();
+            self.remove(oldest);
+            self.stats.evicted += 1;
+        }
+
+        let time = self.clock;
+        self.clock += 1;
+        self.usage.insert(time, n);
+        self.frames.insert(
+            n,
+            Entry {
+                frame,
+                size,
+                last_used: time,
+            },
+        );
+        self.stats.frames += 1;
+        self.stats.bytes += size;
+    }
+
+    /// Removes frame `n` from the cache.
+    fn remove(&mut self, n: usize) {
+        if let Some(entry) = self.frames.remove(&n) {
+            self.usage.remove(&entry.last_used);
+            self.stats.frames -= 1;
+            self.stats.bytes -= entry.size;
+        }
+    }
+
+    /// Records an access to frame `n`, returning the step of the access pattern if the last two
+    /// steps were equal.
+    pub(crate) fn record_access(&mut self, n: usize) -> Option<i64> {
+        let step = self.last.map(|(last, _)| n as i64 - last as i64);
+        let pattern = match (self.last, step) {
+            (Some((_, Some(previous))), Some(step)) if previous == step && step != 0 => Some(step),
+            _ => None,
+        };
+
+        self.last = Some((n, step));
+        pattern
+    }
+
+    /// Returns the frames to prefetch after an access to frame `n`, marking them as in flight.
+    pub(crate) fn prefetch_list(
+        &mut self,
+        n: usize,
+        step: i64,
+        count: usize,
+        num_frames: usize,
+    ) -> Vec<usize> {
+        let mut list = Vec::new();
+
+        for i in 1..=count as i64 {
+            let x = n as i64 + step * i;
+            if x < 0 || x >= num_frames as i64 {
+                break;
+            }
+
+            let x = x as usize;
+            if !self.frames.contains_key(&x) && self.in_flight.insert(x) {
+                list.push(x);
+            }
+        }
+
+        self.stats.prefetched += list.len() as u64;
+        list
+    }
+}
+
+/// Returns the size of the frame data in bytes.
+fn frame_size(frame: &Frame) -> usize {
+    (0..frame.format().plane_count())
+        .map(|plane| frame.stride(plane) * frame.height(plane))
+        .sum()
+}
+
+impl<'core> CachedNode<'core> {
+    /// Wraps `node` in a cache holding up to `budget` bytes of frame data.
+    ///
+    /// Up to `DEFAULT_PREFETCH` frames are requested ahead of sequential accesses.
+    #[inline]
+    pub fn new(node: Node<'core>, budget: usize) -> Self {
+        Self {
+            node,
+            prefetch: DEFAULT_PREFETCH,
+            shared: Arc::new(Shared {
+                budget,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Sets the number of frames requested ahead of sequential accesses. `0` disables
+    /// prefetching.
+    #[inline]
+    pub fn with_prefetch(self, prefetch: usize) -> Self {
+        Self { prefetch, ..self }
+    }
+
+    /// Returns the wrapped node.
+    #[inline]
+    pub fn node(&self) -> &Node<'core> {
+        &self.node
+    }
+
+    /// Returns the cache budget in bytes.
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.shared.budget
+    }
+
+    /// Returns frame `n` from the cache or generates it with `Node::get_frame()`.
+    ///
+    /// Failed frames aren't cached.
+    ///
+    /// # Panics
+    /// Panics if `n` is greater than `i32::MAX`.
+    pub fn get_frame<'error>(&self, n: usize) -> Result<FrameRef<'core>, GetFrameError<'error>> {
+        let (frame, prefetch) = {
+            let mut state = self.shared.state.lock().unwrap();
+
+            let frame = state.touch(n);
+            if frame.is_some() {
+                state.stats.hits += 1;
+            } else {
+                state.stats.misses += 1;
+            }
+
+            let prefetch = match (state.record_access(n), self.prefetch) {
+                (Some(step), count) if count > 0 => {
+                    // Clips of unknown length are only prefetched up to the frame number limit.
+                    let num_frames = self
+                        .node
+                        .info()
+                        .constant_num_frames()
+                        .unwrap_or(i32::MAX as usize + 1);
+                    state.prefetch_list(n, step, count, num_frames)
+                }
+                _ => Vec::new(),
+            };
+
+            (frame, prefetch)
+        };
+
+        // Start the prefetching first so that it runs alongside the generation of frame `n`.
+        for x in prefetch {
+            let shared = self.shared.clone();
+            self.node.get_frame_async(x, move |frame, x, _| {
+                let mut state = shared.state.lock().unwrap();
+                state.in_flight.remove(&x);
+                if let Ok(frame) = frame {
+                    state.insert(x, frame, shared.budget);
+                }
+            });
+        }
+
+        if let Some(frame) = frame {
+            return Ok(frame);
+        }
+
+        let frame = self.node.get_frame(n)?;
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .insert(n, frame.clone(), self.shared.budget);
+        Ok(frame)
+    }
+
+    /// Returns `true` if frame `n` is in the cache.
+    ///
+    /// This doesn't count as an access.
+    #[inline]
+    pub fn contains(&self, n: usize) -> bool {
+        self.shared.state.lock().unwrap().frames.contains_key(&n)
+    }
+
+    /// Returns the cache statistics.
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.shared.state.lock().unwrap().stats
+    }
+
+    /// Resets the hit, miss, prefetch and eviction counters.
+    #[inline]
+    pub fn reset_stats(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.stats = CacheStats {
+            frames: state.stats.frames,
+            bytes: state.stats.bytes,
+            ..CacheStats::default()
+        };
+    }
+
+    /// Removes all frames from the cache and forgets the access pattern.
+    ///
+    /// Frames which are being prefetched are still cached once they are ready.
+    pub fn clear(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.frames.clear();
+        state.usage.clear();
+        state.last = None;
+        state.stats.frames = 0;
+        state.stats.bytes = 0;
+    }
+}
@@ -3,14 +3,34 @@ use std::error::Error;
 use std::ffi::CStr;
 use std::fmt;
 
+/// The separator between nested error messages, as produced by filters written with this crate.
+const CAUSE_SEPARATOR: &str = "\nCaused by: ";
+
 /// A container for a `get_frame` error.
+///
+/// VapourSynth reports the failure of a filter as a single message which also contains the
+/// messages of the errors that caused it. This type parses the message into a chain of
+/// `FilterError`s accessible via `source()`.
 #[derive(Debug)]
-pub struct GetFrameError<'a>(Cow<'a, CStr>);
+pub struct GetFrameError<'a> {
+    raw: Cow<'a, CStr>,
+    n: usize,
+    node_id: usize,
+    message: String,
+    cause: Option<FilterError>,
+}
+
+/// A nested error message from a `GetFrameError`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FilterError {
+    message: String,
+    cause: Option<Box<FilterError>>,
+}
 
 impl<'a> fmt::Display for GetFrameError<'a> {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0.to_string_lossy())
+        write!(f, "Couldn't get frame {}: {}", self.n, self.message)
     }
 }
 
@@ -19,18 +39,107 @@ impl<'a> Error for GetFrameError<'a> {
     fn description(&self) -> &str {
         "VapourSynth error"
     }
+
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause.as_ref().map(|x| x as _)
+    }
+}
+
+impl fmt::Display for FilterError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for FilterError {
+    #[inline]
+    fn description(&self) -> &str {
+        "VapourSynth filter error"
+    }
+
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause.as_ref().map(|x| &**x as _)
+    }
 }
 
 impl<'a> GetFrameError<'a> {
-    /// Creates a new `GetFrameError` with the given error message.
+    /// Creates a new `GetFrameError` with the given error message for frame `n` of the node
+    /// with the given identifier.
+    pub(crate) fn new(raw: Cow<'a, CStr>, n: usize, node_id: usize) -> Self {
+        let (message, cause) = {
+            let text = raw.to_string_lossy();
+            let mut messages = text
+                .split(CAUSE_SEPARATOR)
+                .map(|x| x.trim_end().to_owned())
+                .collect::<Vec<_>>();
+
+            let message = messages.remove(0);
+            let cause = messages.into_iter().rev().fold(None, |cause, message| {
+                Some(FilterError {
+                    message,
+                    cause: cause.map(Box::new),
+                })
+            });
+
+            (message, cause)
+        };
+
+        Self {
+            raw,
+            n,
+            node_id,
+            message,
+            cause,
+        }
+    }
+
+    /// Returns the number of the frame which couldn't be retrieved.
+    #[inline]
+    pub fn frame_number(&self) -> usize {
+        self.n
+    }
+
+    /// Returns the identifier of the node the frame was requested from.
+    ///
+    /// This is equal to `Node::id()` of that node. VapourSynth API 3 doesn't expose node names,
+    /// so the identifier is the only way to tell which node failed.
+    #[inline]
+    pub fn node_id(&self) -> usize {
+        self.node_id
+    }
+
+    /// Returns the top-level error message, without the nested error messages.
     #[inline]
-    pub(crate) fn new(message: Cow<'a, CStr>) -> Self {
-        GetFrameError(message)
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the first nested error, if any.
+    #[inline]
+    pub fn filter_error(&self) -> Option<&FilterError> {
+        self.cause.as_ref()
     }
 
     /// Consumes this error, returning its underlying error message.
     #[inline]
     pub fn into_inner(self) -> Cow<'a, CStr> {
-        self.0
+        self.raw
+    }
+}
+
+impl FilterError {
+    /// Returns the error message.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the nested error which caused this error, if any.
+    #[inline]
+    pub fn cause(&self) -> Option<&FilterError> {
+        self.cause.as_deref()
     }
 }
@@ -10,12 +10,23 @@ use std::{mem, panic};
 use vapoursynth_sys as ffi;
 
 use api::API;
+use core::{CoreRef, CreateFilterError};
 use frame::FrameRef;
-use plugins::FrameContext;
+use map::Map;
+use plugins::{Error, Filter, FrameContext};
 use video_info::VideoInfo;
 
+mod adaptors;
+use self::adaptors::{FrameEval, MapFrames, ModifyFrame};
+
+pub(crate) mod cache;
+pub use self::cache::{CacheStats, CachedNode, DEFAULT_PREFETCH};
+
 mod errors;
-pub use self::errors::GetFrameError;
+pub use self::errors::{FilterError, GetFrameError};
+
+mod temporal;
+pub use self::temporal::EdgeMode;
 
 bitflags! {
     /// Node flags.
@@ -103,6 +114,15 @@ impl<'core> Node<'core> {
         }
     }
 
+    /// Returns an identifier of this node.
+    ///
+    /// The identifier is equal for all references to the same node (output) and stays unique
+    /// while the node is alive.
+    #[inline]
+    pub fn id(&self) -> usize {
+        unsafe { API::get_cached().get_video_info(self.handle.as_ptr()) as usize }
+    }
+
     /// Generates a frame directly.
     ///
     /// The `'error` lifetime is unbounded because this function always returns owned data.
@@ -124,7 +144,7 @@ impl<'core> Node<'core> {
         if handle.is_null() {
             // TODO: remove this extra allocation by reusing `Box<[c_char]>`.
             let error = unsafe { CStr::from_ptr(err_buf.as_ptr()) }.to_owned();
-            Err(GetFrameError::new(Cow::Owned(error)))
+            Err(GetFrameError::new(Cow::Owned(error), n as usize, self.id()))
         } else {
             Ok(unsafe { FrameRef::from_ptr(handle) })
         }
@@ -190,20 +210,20 @@ impl<'core> Node<'core> {
             let user_data = Box::from_raw(user_data as *mut CallbackData<'static>);
 
             let closure = panic::AssertUnwindSafe(move || {
+                let node = Node::from_ptr(node);
+
+                debug_assert!(n >= 0);
+                let n = n as usize;
+
                 let frame = if frame.is_null() {
                     debug_assert!(!error_msg.is_null());
                     let error_msg = Cow::Borrowed(CStr::from_ptr(error_msg));
-                    Err(GetFrameError::new(error_msg))
+                    Err(GetFrameError::new(error_msg, n, node.id()))
                 } else {
                     debug_assert!(error_msg.is_null());
                     Ok(FrameRef::from_ptr(frame))
                 };
 
-                let node = Node::from_ptr(node);
-
-                debug_assert!(n >= 0);
-                let n = n as usize;
-
                 user_data.callback.call(frame, n, node);
             });
 
@@ -276,4 +296,137 @@ impl<'core> Node<'core> {
             Some(unsafe { FrameRef::from_ptr(ptr) })
         }
     }
+
+    /// Creates a node which passes every frame of this node through `callback`.
+    ///
+    /// The callback receives the frame number and the source frame and returns the output frame,
+    /// which must match the video info of this node. It's called from VapourSynth worker threads,
+    /// possibly in parallel.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # extern crate vapoursynth;
+    /// # use std::error::Error;
+    /// # use vapoursynth::core::CoreRef;
+    /// # use vapoursynth::prelude::*;
+    /// # fn foo<'core>(core: CoreRef<'core>, node: Node<'core>) -> Result<(), Box<dyn Error>> {
+    /// let inverted = node.map_frames(core, move |_n, frame| {
+    ///     let mut frame = frame.into_mut(core);
+    ///     for plane in 0..frame.format().plane_count() {
+    ///         for row in 0..frame.height(plane) {
+    ///             for pixel in frame.plane_row_mut::<u8>(plane, row) {
+    ///                 *pixel = 255 - *pixel;
+    ///             }
+    ///         }
+    ///     }
+    ///     Ok(frame.into())
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # fn main() {}
+    /// ```
+    #[inline]
+    pub fn map_frames<F>(
+        &self,
+        core: CoreRef<'core>,
+        callback: F,
+    ) -> Result<Node<'core>, CreateFilterError>
+    where
+        F: Fn(usize, FrameRef<'core>) -> Result<FrameRef<'core>, Error> + Send + Sync + 'core,
+    {
+        let filter = MapFrames {
+            source: self.clone(),
+            callback,
+        };
+
+        let args = map! { "clip" => self }.unwrap();
+        create_adaptor(core, "MapFrames", &args, Box::new(filter))
+    }
+
+    /// Creates a node which lets `callback` modify the properties of every frame of this node.
+    ///
+    /// The callback receives the frame number and the properties of a copy of the source frame.
+    /// It's called from VapourSynth worker threads, possibly in parallel.
+    #[inline]
+    pub fn map_props<F>(
+        &self,
+        core: CoreRef<'core>,
+        callback: F,
+    ) -> Result<Node<'core>, CreateFilterError>
+    where
+        F: Fn(usize, &mut Map) -> Result<(), Error> + Send + Sync + 'core,
+    {
+        let filter = MapFrames {
+            source: self.clone(),
+            callback: move |n, frame: FrameRef<'core>| {
+                frame.with_props_mut(core, |props| callback(n, props))
+            },
+        };
+
+        let args = map! { "clip" => self }.unwrap();
+        create_adaptor(core, "MapProps", &args, Box::new(filter))
+    }
+
+    /// Creates a node which takes every frame from a clip selected by `callback`.
+    ///
+    /// This is the equivalent of `std.FrameEval`. The callback receives the frame number and
+    /// returns the clip to take the frame with the same number from. The output has the video info
+    /// of this node, so the returned clips should match it.
+    ///
+    /// The callback is called from VapourSynth worker threads, possibly in parallel.
+    #[inline]
+    pub fn frame_eval<F>(
+        &self,
+        core: CoreRef<'core>,
+        callback: F,
+    ) -> Result<Node<'core>, CreateFilterError>
+    where
+        F: Fn(usize) -> Result<Node<'core>, Error> + Send + Sync + 'core,
+    {
+        let filter = FrameEval {
+            template: self.clone(),
+            callback,
+        };
+
+        let args = map! { "clip" => self }.unwrap();
+        create_adaptor(core, "FrameEval", &args, Box::new(filter))
+    }
+
+    /// Creates a node which produces every frame from the frames of `clips` using `callback`.
+    ///
+    /// This is the equivalent of `std.ModifyFrame`. The callback receives the frame number and
+    /// the frames with that number from each of `clips`, in the same order. The output has the
+    /// video info of this node, and the returned frames should match it.
+    ///
+    /// The callback is called from VapourSynth worker threads, possibly in parallel.
+    #[inline]
+    pub fn modify_frame<F>(
+        &self,
+        core: CoreRef<'core>,
+        clips: &[Node<'core>],
+        callback: F,
+    ) -> Result<Node<'core>, CreateFilterError>
+    where
+        F: Fn(usize, &[FrameRef<'core>]) -> Result<FrameRef<'core>, Error> + Send + Sync + 'core,
+    {
+        let filter = ModifyFrame {
+            template: self.clone(),
+            clips: clips.to_vec(),
+            callback,
+        };
+
+        let args = map! { "clip" => self, "clips" => clips }.unwrap();
+        create_adaptor(core, "ModifyFrame", &args, Box::new(filter))
+    }
+}
+
+/// Creates the node of an adaptor filter, recording `args` for `graph::export()`.
+fn create_adaptor<'core>(
+    core: CoreRef<'core>,
+    name: &str,
+    args: &Map<'core>,
+    filter: Box<dyn Filter<'core> + 'core>,
+) -> Result<Node<'core>, CreateFilterError> {
+    let mut nodes = core.create_filter_with_args(name, args, filter)?;
+    Ok(nodes.swap_remove(0))
 }
@@ -0,0 +1,156 @@
+//! Helpers for filters working on windows of frames.
+
+use frame::FrameRef;
+use node::Node;
+use plugins::FrameContext;
+
+/// Specifies how frame numbers outside of a clip are mapped into it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum EdgeMode {
+    /// Frames before the start are replaced with the first frame, frames past the end are
+    /// replaced with the last frame.
+    Clamp,
+    /// Frame numbers are reflected at the clip edges without repeating the edge frames, so frame
+    /// `-1` becomes frame `1` and frame `num_frames` becomes frame `num_frames - 2`.
+    Mirror,
+}
+
+impl EdgeMode {
+    /// Maps the (possibly out of range) frame number `n` into a clip of `num_frames` frames.
+    ///
+    /// # Panics
+    /// Panics if `num_frames` is zero.
+    pub fn frame_number(self, n: i64, num_frames: usize) -> usize {
+        assert!(num_frames > 0);
+
+        let last = num_frames as i128 - 1;
+        let n = i128::from(n);
+
+        let n = match self {
+            EdgeMode::Clamp => n.max(0).min(last),
+            EdgeMode::Mirror if last == 0 => 0,
+            EdgeMode::Mirror => {
+                let period = 2 * last;
+                let n = n.rem_euclid(period);
+                if n > last {
+                    period - n
+                } else {
+                    n
+                }
+            }
+        };
+
+        n as usize
+    }
+
+    /// Returns the frame numbers of the window of `radius` frames around frame `n`.
+    #[inline]
+    fn window(self, n: usize, radius: usize, num_frames: usize) -> impl Iterator<Item = usize> {
+        let n = n as i64;
+        let radius = radius as i64;
+        (n - radius..=n + radius).map(move |x| self.frame_number(x, num_frames))
+    }
+}
+
+impl<'core> Node<'core> {
+    /// Returns the number of frames to use for the edge handling.
+    ///
+    /// Clips of unknown length are only clamped or mirrored at the start.
+    #[inline]
+    fn edge_num_frames(&self) -> usize {
+        self.info()
+            .constant_num_frames()
+            .unwrap_or(i32::MAX as usize + 1)
+    }
+
+    /// Requests the window of frames from `n - radius` to `n + radius`, mapping the frame
+    /// numbers outside of the clip according to `edge`.
+    ///
+    /// This is only used in filters' "get frame" functions. Every frame is requested once, in
+    /// ascending order. The frames can then be retrieved using `get_frames_filter()` with the
+    /// same arguments.
+    ///
+    /// # Panics
+    /// Panics if `n + radius` is greater than `i32::MAX`.
+    pub fn request_range_filter(
+        &self,
+        context: FrameContext,
+        n: usize,
+        radius: usize,
+        edge: EdgeMode,
+    ) {
+        assert!(n.saturating_add(radius) <= i32::MAX as usize);
+
+        let mut frames = edge
+            .window(n, radius, self.edge_num_frames())
+            .collect::<Vec<_>>();
+        frames.sort_unstable();
+        frames.dedup();
+
+        for n in frames {
+            self.request_frame_filter(context, n);
+        }
+    }
+
+    /// Retrieves the window of frames previously requested with `request_range_filter()`.
+    ///
+    /// The returned `Vec` contains `2 * radius + 1` frames, frame `n` being at index `radius`.
+    /// Returns `None` if any of the frames weren't requested.
+    ///
+    /// # Panics
+    /// Panics if `n + radius` is greater than `i32::MAX`.
+    pub fn get_frames_filter(
+        &self,
+        context: FrameContext,
+        n: usize,
+        radius: usize,
+        edge: EdgeMode,
+    ) -> Option<Vec<FrameRef<'core>>> {
+        assert!(n.saturating_add(radius) <= i32::MAX as usize);
+
+        edge.window(n, radius, self.edge_num_frames())
+            .map(|n| self.get_frame_filter(context, n))
+            .collect()
+    }
+
+    /// Calls `request_range_filter()` for each of `nodes`.
+    ///
+    /// The frame numbers are mapped into each clip separately, so clips of different lengths are
+    /// handled correctly.
+    ///
+    /// # Panics
+    /// Panics if `n + radius` is greater than `i32::MAX`.
+    #[inline]
+    pub fn request_range_filter_multi(
+        nodes: &[Node<'core>],
+        context: FrameContext,
+        n: usize,
+        radius: usize,
+        edge: EdgeMode,
+    ) {
+        for node in nodes {
+            node.request_range_filter(context, n, radius, edge);
+        }
+    }
+
+    /// Calls `get_frames_filter()` for each of `nodes`.
+    ///
+    /// Returns the windows in the order of `nodes`, or `None` if any of the frames weren't
+    /// requested.
+    ///
+    /// # Panics
+    /// Panics if `n + radius` is greater than `i32::MAX`.
+    #[inline]
+    pub fn get_frames_filter_multi(
+        nodes: &[Node<'core>],
+        context: FrameContext,
+        n: usize,
+        radius: usize,
+        edge: EdgeMode,
+    ) -> Option<Vec<Vec<FrameRef<'core>>>> {
+        nodes
+            .iter()
+            .map(|node| node.get_frames_filter(context, n, radius, edge))
+            .collect()
+    }
+}
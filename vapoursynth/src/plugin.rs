@@ -6,7 +6,7 @@ use std::ops::Deref;
 use std::ptr::NonNull;
 use vapoursynth_sys as ffi;
 
-use api::API;
+use api::{Capability, UnsupportedError, API};
 use map::{Map, OwnedMap};
 use plugins::{self, FilterFunction};
 
@@ -48,14 +48,18 @@ impl<'core> Plugin<'core> {
     /// location of the plugin, i.e. there are no symbolic links in the path.
     ///
     /// Path elements are always delimited with forward slashes.
-    #[cfg(feature = "gte-vapoursynth-api-31")]
+    ///
+    /// Requires `Capability::PluginPath`.
     #[inline]
-    pub fn path(&self) -> Option<&'core CStr> {
-        let ptr = unsafe { API::get_cached().get_plugin_path(self.handle.as_ptr()) };
+    pub fn path(&self) -> Result<Option<&'core CStr>, UnsupportedError> {
+        let api = unsafe { API::get_cached() };
+        api.require(Capability::PluginPath)?;
+
+        let ptr = unsafe { api.get_plugin_path(self.handle.as_ptr()) };
         if ptr.is_null() {
-            None
+            Ok(None)
         } else {
-            Some(unsafe { CStr::from_ptr(ptr) })
+            Ok(Some(unsafe { CStr::from_ptr(ptr) }))
         }
     }
 
@@ -95,6 +99,8 @@ impl<'core> Plugin<'core> {
         let data = Box::new(plugins::ffi::FilterFunctionData::<F> {
             filter_function,
             name: name_cstring,
+            // Functions registered from a filter function share the state of its plugin.
+            state: plugins::state::current_state(),
         });
 
         unsafe {
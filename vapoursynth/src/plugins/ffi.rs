@@ -1,17 +1,25 @@
 //! Internal stuff for plugin FFI handling.
-use failure::Error;
+use std::any::Any;
+use std::error;
 use std::ffi::CString;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::c_void;
 use std::ptr::{self, NonNull};
+use std::sync::{Arc, OnceLock};
 use std::{mem, panic, process};
 use vapoursynth_sys as ffi;
 
 use api::API;
 use core::CoreRef;
 use frame::FrameRef;
+use graph;
 use map::{Map, MapRef, MapRefMut};
-use plugins::{Filter, FilterFunction, FrameContext, Metadata};
+use plugins::profiling::{self, CallTimer};
+use plugins::state::{self, AnyState};
+use plugins::{
+    Error, Filter, FilterFunction, FormatConstraint, FormatConstraintError, FrameContext, Metadata,
+    NodeContext,
+};
 use video_info::VideoInfo;
 
 /// Container for the internal filter function data.
@@ -20,20 +28,80 @@ pub(crate) struct FilterFunctionData<F: FilterFunction> {
     // Store the name since it's supposed to be the same between two invocations (register and
     // create_filter).
     pub name: CString,
+    // The shared state of the plugin which registered this function.
+    pub state: Option<AnyState>,
 }
 
-/// Pushes the error backtrace into the given string.
-fn push_backtrace(buf: &mut String, err: &Error) {
-    for cause in err.iter_causes() {
-        buf.push_str(&format!("Caused by: {}", cause));
+/// The instance data of a filter.
+pub(crate) struct FilterInstance<'core> {
+    filter: Box<dyn Filter<'core> + 'core>,
+    name: String,
+    // The profiling statistics of the filter, looked up on the first profiled call.
+    profile: OnceLock<Arc<profiling::Entry>>,
+}
+
+impl<'core> FilterInstance<'core> {
+    /// Wraps a filter created with the given name.
+    #[inline]
+    pub(crate) fn new(filter: Box<dyn Filter<'core> + 'core>, name: &str) -> Self {
+        profiling::init_from_env();
+
+        Self {
+            filter,
+            name: name.to_owned(),
+            profile: OnceLock::new(),
+        }
+    }
+
+    /// Returns the profiling statistics of the filter, counting the instance on the first call.
+    #[inline]
+    fn profile(&self) -> &profiling::Entry {
+        self.profile.get_or_init(|| profiling::register(&self.name))
+    }
+}
+
+/// Pushes the error causes and backtrace into the given string.
+pub(crate) fn push_backtrace(buf: &mut String, err: &(dyn error::Error + 'static)) {
+    let mut source = err.source();
+    while let Some(cause) = source {
+        buf.push_str(&format!("\nCaused by: {}", cause));
+        source = cause.source();
+    }
+
+    // `anyhow` errors, also when converted into a boxed error, have their backtrace at the end of
+    // their `Debug` output.
+    let debug = format!("{:?}", err);
+    if let Some(index) = debug.find("\n\nStack backtrace:\n") {
+        buf.push('\n');
+        buf.push_str(&debug[index + 2..]);
+    }
+}
+
+/// Returns the text of a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(&message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Returns the error message for a panic in `function`.
+///
+/// With the `abort-on-filter-panic` feature the process is aborted instead.
+fn panic_error(function: &str, payload: &(dyn Any + Send)) -> String {
+    if cfg!(feature = "abort-on-filter-panic") {
+        process::abort();
     }
 
-    buf.push_str(&format!("{}", err.backtrace()));
+    format!("Panic in {}: {}", function, panic_message(payload))
 }
 
 /// Sets the video info of the output node of this filter.
-unsafe extern "system" fn init(
-    _in_: *mut ffi::VSMap,
+pub(crate) unsafe extern "system" fn init(
+    in_: *mut ffi::VSMap,
     out: *mut ffi::VSMap,
     instance_data: *mut *mut c_void,
     node: *mut ffi::VSNode,
@@ -44,59 +112,88 @@ unsafe extern "system" fn init(
         let core = CoreRef::from_ptr(core);
         // The actual lifetime isn't 'static, it's 'core, but we don't really have a way of
         // retrieving it.
-        let filter =
-            Box::from_raw(*(instance_data as *mut *mut Box<dyn Filter<'static> + 'static>));
+        let filter = &(**(instance_data as *const *const FilterInstance<'static>)).filter;
 
-        let vi = filter
-            .video_info(API::get_cached(), core)
-            .into_iter()
+        let video_info = filter.video_info(API::get_cached(), core);
+        let vi = video_info
+            .iter()
+            .copied()
             .map(VideoInfo::ffi_type)
             .collect::<Vec<_>>();
         API::get_cached().set_video_info(&vi, node);
 
-        mem::forget(filter);
-    };
+        let args = MapRef::from_ptr(in_);
+        let context = NodeContext::new(&args, &video_info);
+        if let Err(err) = filter.on_init(API::get_cached(), core, context) {
+            let mut buf = format!("Error in Filter::on_init(): {}", err);
+            push_backtrace(&mut buf, &*err);
 
-    if panic::catch_unwind(closure).is_err() {
-        let closure = move || {
-            // We have to leak filter here because we can't guarantee that it's in a consistent
-            // state after a panic.
-            //
-            // Just set the error message.
             let mut out = MapRefMut::from_ptr(out);
-            out.set_error("Panic during Filter::video_info()");
-        };
+            out.set_error(&buf.replace('\0', "\\0")).unwrap();
+            return false;
+        }
+
+        true
+    };
+
+    let initialized = match panic::catch_unwind(closure) {
+        Ok(initialized) => initialized,
+        Err(payload) => {
+            let message = panic_error("Filter::video_info() or Filter::on_init()", &*payload);
+            let closure = move || {
+                let mut out = MapRefMut::from_ptr(out);
+                out.set_error(&message.replace('\0', "\\0")).unwrap();
+            };
+
+            if panic::catch_unwind(closure).is_err() {
+                process::abort();
+            }
 
+            false
+        }
+    };
+
+    // VapourSynth doesn't call `free()` when the initialization fails.
+    if !initialized {
+        let closure = move || drop_instance(mem::replace(&mut *instance_data, ptr::null_mut()));
+
+        // There's nowhere to report an error to, and the filter is half-dropped.
         if panic::catch_unwind(closure).is_err() {
             process::abort();
         }
     }
 }
 
+/// Calls `Filter::on_free()`, drops the filter instance and removes its graph records.
+unsafe fn drop_instance(instance_data: *mut c_void) {
+    graph::forget(instance_data as usize);
+
+    // The actual lifetime isn't 'static, it's 'core, but we don't really have a way of
+    // retrieving it.
+    let mut instance = Box::from_raw(instance_data as *mut FilterInstance<'static>);
+    instance.filter.on_free();
+}
+
 /// Drops the filter.
-unsafe extern "system" fn free(
+pub(crate) unsafe extern "system" fn free(
     instance_data: *mut c_void,
     core: *mut ffi::VSCore,
     _vsapi: *const ffi::VSAPI,
 ) {
-    let closure = move || {
-        // The actual lifetime isn't 'static, it's 'core, but we don't really have a way of
-        // retrieving it.
-        let filter = Box::from_raw(instance_data as *mut Box<dyn Filter<'static> + 'static>);
-        drop(filter);
-    };
+    let closure = move || drop_instance(instance_data);
 
+    // There's nowhere to report an error to, and the filter is half-dropped.
     if panic::catch_unwind(closure).is_err() {
         process::abort();
     }
 }
 
 /// Calls `Filter::get_frame_initial()` and `Filter::get_frame()`.
-unsafe extern "system" fn get_frame(
+pub(crate) unsafe extern "system" fn get_frame(
     n: i32,
     activation_reason: i32,
     instance_data: *mut *mut c_void,
-    _frame_data: *mut *mut c_void,
+    frame_data: *mut *mut c_void,
     frame_ctx: *mut ffi::VSFrameContext,
     core: *mut ffi::VSCore,
     _vsapi: *const ffi::VSAPI,
@@ -104,19 +201,23 @@ unsafe extern "system" fn get_frame(
     let closure = move || {
         let api = API::get_cached();
         let core = CoreRef::from_ptr(core);
-        let context = FrameContext::from_ptr(frame_ctx);
+        let context = FrameContext::from_ptr(frame_ctx, frame_data);
 
         // The actual lifetime isn't 'static, it's 'core, but we don't really have a way of
         // retrieving it.
-        let filter =
-            Box::from_raw(*(instance_data as *mut *mut Box<dyn Filter<'static> + 'static>));
+        let instance = &**(instance_data as *const *const FilterInstance<'static>);
+        let filter = &instance.filter;
 
         debug_assert!(n >= 0);
         let n = n as usize;
 
         let rv = match activation_reason {
             x if x == ffi::VSActivationReason::arInitial as _ => {
-                match filter.get_frame_initial(api, core, context, n) {
+                let timer = CallTimer::start(|| instance.profile(), true);
+                let result = filter.get_frame_initial(api, core, context, n);
+                drop(timer);
+
+                match result {
                     Ok(Some(frame)) => {
                         let ptr = frame.deref().deref() as *const _;
                         // The ownership is transferred to the caller.
@@ -127,9 +228,9 @@ unsafe extern "system" fn get_frame(
                     Err(err) => {
                         let mut buf = String::new();
 
-                        buf += &format!("Error in Filter::get_frame_initial(): {}", err.as_fail());
+                        buf += &format!("Error in Filter::get_frame_initial(): {}", err);
 
-                        push_backtrace(&mut buf, &err);
+                        push_backtrace(&mut buf, &*err);
 
                         let buf = CString::new(buf.replace('\0', "\\0")).unwrap();
                         api.set_filter_error(buf.as_ptr(), frame_ctx);
@@ -139,7 +240,12 @@ unsafe extern "system" fn get_frame(
                 }
             }
             x if x == ffi::VSActivationReason::arAllFramesReady as _ => {
-                match filter.get_frame(api, core, context, n) {
+                let timer = CallTimer::start(|| instance.profile(), false);
+                let result = filter.get_frame(api, core, context, n);
+                drop(timer);
+                context.free_frame_data();
+
+                match result {
                     Ok(frame) => {
                         let ptr = frame.deref().deref() as *const _;
                         // The ownership is transferred to the caller.
@@ -149,9 +255,9 @@ unsafe extern "system" fn get_frame(
                     Err(err) => {
                         let mut buf = String::new();
 
-                        buf += &format!("{}", err.as_fail());
+                        buf += &format!("{}", err);
 
-                        push_backtrace(&mut buf, &err);
+                        push_backtrace(&mut buf, &*err);
 
                         let buf = CString::new(buf.replace('\0', "\\0")).unwrap();
                         api.set_filter_error(buf.as_ptr(), frame_ctx);
@@ -160,20 +266,83 @@ unsafe extern "system" fn get_frame(
                     }
                 }
             }
+            x if x == ffi::VSActivationReason::arError as _ => {
+                // One of the requested frames failed, so `get_frame()` won't be called.
+                context.free_frame_data();
+                ptr::null()
+            }
             _ => ptr::null(),
         };
 
-        mem::forget(filter);
-
         rv
     };
 
     match panic::catch_unwind(closure) {
         Ok(frame) => frame,
-        Err(_) => process::abort(),
+        Err(payload) => {
+            // The filter is only accessed through a shared reference, so it can be used for
+            // further requests (any `Mutex` it holds is poisoned by the panic).
+            let function = if activation_reason == ffi::VSActivationReason::arInitial as _ {
+                "Filter::get_frame_initial()"
+            } else {
+                "Filter::get_frame()"
+            };
+            let message = panic_error(function, &*payload);
+
+            let closure = move || {
+                // VapourSynth won't call the filter for this request anymore.
+                FrameContext::from_ptr(frame_ctx, frame_data).free_frame_data();
+
+                let buf = CString::new(message.replace('\0', "\\0")).unwrap();
+                API::get_cached().set_filter_error(buf.as_ptr(), frame_ctx);
+            };
+
+            if panic::catch_unwind(closure).is_err() {
+                process::abort();
+            }
+
+            ptr::null()
+        }
     }
 }
 
+/// Checks the clip arguments against the format constraints of the filter function.
+fn check_format_constraints<F: FilterFunction>(
+    filter_function: &F,
+    args: &Map,
+) -> Result<(), FormatConstraintError> {
+    for &(name, ref constraint) in filter_function.format_constraints() {
+        // Absent optional arguments are fine.
+        if let Ok(nodes) = args.get_node_iter(name) {
+            for node in nodes {
+                constraint.check(name, &node.info())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the name of the first format constraint which doesn't name a `clip` argument in the
+/// `args` string of a filter function.
+pub(crate) fn invalid_constraint_argument<'a>(
+    args: &str,
+    constraints: &[(&'a str, FormatConstraint)],
+) -> Option<&'a str> {
+    let is_clip_argument = |name: &str| {
+        args.split(';').any(|arg| {
+            let mut parts = arg.split(':');
+            parts.next() == Some(name)
+                && parts.next().map(|ty| ty.trim_end_matches("[]")) == Some("clip")
+        })
+    };
+
+    constraints
+        .iter()
+        .map(|&(name, _)| name)
+        .find(|&name| !is_clip_argument(name))
+}
+
 /// Creates a new instance of the filter.
 pub(crate) unsafe extern "system" fn create<F: FilterFunction>(
     in_: *const ffi::VSMap,
@@ -188,10 +357,19 @@ pub(crate) unsafe extern "system" fn create<F: FilterFunction>(
         let args = MapRef::from_ptr(in_);
         let mut out = MapRefMut::from_ptr(out);
         let core = CoreRef::from_ptr(core);
-        let data = Box::from_raw(user_data as *mut FilterFunctionData<F>);
-
-        let filter = match data.filter_function.create(API::get_cached(), core, &args) {
-            Ok(Some(filter)) => Some(Box::new(filter)),
+        let data = &*(user_data as *const FilterFunctionData<F>);
+
+        let filter = match check_format_constraints(&data.filter_function, &args)
+            .map_err(Error::from)
+            .and_then(|()| {
+                state::with_state(data.state.as_ref(), || {
+                    data.filter_function.create(API::get_cached(), core, &args)
+                })
+            }) {
+            Ok(Some(filter)) => Some(Box::new(FilterInstance::new(
+                filter,
+                &data.name.to_string_lossy(),
+            ))),
             Ok(None) => None,
             Err(err) => {
                 let mut buf = String::new();
@@ -199,10 +377,10 @@ pub(crate) unsafe extern "system" fn create<F: FilterFunction>(
                 buf += &format!(
                     "Error in Filter::create() of {}: {}",
                     data.name.to_str().unwrap(),
-                    err.as_fail()
+                    err
                 );
 
-                push_backtrace(&mut buf, &err);
+                push_backtrace(&mut buf, &*err);
 
                 out.set_error(&buf.replace('\0', "\\0")).unwrap();
                 None
@@ -210,6 +388,7 @@ pub(crate) unsafe extern "system" fn create<F: FilterFunction>(
         };
 
         if let Some(filter) = filter {
+            let instance = Box::into_raw(filter);
             API::get_cached().create_filter(
                 in_,
                 out.deref_mut().deref_mut(),
@@ -219,17 +398,38 @@ pub(crate) unsafe extern "system" fn create<F: FilterFunction>(
                 Some(free),
                 ffi::VSFilterMode::fmParallel,
                 ffi::VSNodeFlags(0),
-                Box::into_raw(filter) as *mut _,
+                instance as *mut _,
                 core.ptr(),
             );
-        }
 
-        mem::forget(data);
+            if out.error().is_none() {
+                let nodes = out.get_node_iter("clip").unwrap().collect::<Vec<_>>();
+                graph::record(
+                    instance as usize,
+                    &data.name.to_string_lossy(),
+                    &args,
+                    &nodes,
+                );
+            }
+        }
     };
 
-    if panic::catch_unwind(closure).is_err() {
-        // The `FilterFunction` might have been left in an inconsistent state, so we have to abort.
-        process::abort();
+    if let Err(payload) = panic::catch_unwind(closure) {
+        let data = &*(user_data as *const FilterFunctionData<F>);
+        let function = format!(
+            "FilterFunction::create() of {}",
+            data.name.to_string_lossy()
+        );
+        let message = panic_error(&function, &*payload);
+
+        let closure = move || {
+            let mut out = MapRefMut::from_ptr(out);
+            out.set_error(&message.replace('\0', "\\0")).unwrap();
+        };
+
+        if panic::catch_unwind(closure).is_err() {
+            process::abort();
+        }
     }
 }
 
@@ -275,6 +475,22 @@ pub unsafe fn call_register_func<F: FilterFunction>(
     register_func: *const c_void,
     plugin: *mut c_void,
     filter_function: F,
+) {
+    call_register_func_with_state(register_func, plugin, filter_function, None);
+}
+
+/// Registers the filter `F` with the given plugin state.
+///
+/// This function is for internal use only.
+///
+/// # Safety
+/// The caller must ensure the pointers are valid.
+#[inline]
+pub unsafe fn call_register_func_with_state<F: FilterFunction>(
+    register_func: *const c_void,
+    plugin: *mut c_void,
+    filter_function: F,
+    state: Option<Arc<dyn Any + Send + Sync>>,
 ) {
     let register_func = *(&register_func as *const _ as *const ffi::VSRegisterFunction);
 
@@ -283,9 +499,21 @@ pub unsafe fn call_register_func<F: FilterFunction>(
     let args_cstring = CString::new(filter_function.args())
         .expect("Couldn't convert the filter args to a CString");
 
+    // Catch typos in the constraints before the filter is ever created.
+    if let Some(name) =
+        invalid_constraint_argument(filter_function.args(), filter_function.format_constraints())
+    {
+        panic!(
+            "The format constraints of {} name `{}`, which isn't a clip argument",
+            filter_function.name(),
+            name
+        );
+    }
+
     let data = Box::new(FilterFunctionData {
         filter_function,
         name: name_cstring,
+        state,
     });
 
     register_func(
@@ -307,6 +535,11 @@ pub unsafe fn call_register_func<F: FilterFunction>(
 /// Following it is a list of values implementing `FilterFunction`, those are the filter functions
 /// the plugin will export.
 ///
+/// Optionally, a `state` expression can be given before the list. It's evaluated every time the
+/// plugin is registered with a core, and the resulting value is shared by all filter functions of
+/// that registration through `plugins::plugin_state()`. VapourSynth never unregisters functions, so
+/// the state is never dropped; use `Filter::on_free()` for deterministic teardown.
+///
 /// # Example
 /// ```ignore
 /// export_vapoursynth_plugin! {
@@ -319,8 +552,60 @@ pub unsafe fn call_register_func<F: FilterFunction>(
 ///     [SampleFilterFunction::new(), OtherFunction::new()]
 /// }
 /// ```
+///
+/// With a shared state:
+///
+/// ```ignore
+/// export_vapoursynth_plugin! {
+///     Metadata {
+///         identifier: "com.example.invert",
+///         namespace: "invert",
+///         name: "Invert Example Plugin",
+///         read_only: true,
+///     },
+///     state: SharedCache::new(),
+///     [SampleFilterFunction::new(), OtherFunction::new()]
+/// }
+///
+/// // In FilterFunction::create():
+/// let cache = plugin_state::<SharedCache>().unwrap();
+/// ```
 #[macro_export]
 macro_rules! export_vapoursynth_plugin {
+    ($metadata:expr, state: $state:expr, [$($filter:expr),*$(,)*]) => (
+        use ::std::os::raw::c_void;
+
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub unsafe extern "system" fn VapourSynthPluginInit(
+            config_func: *const c_void,
+            register_func: *const c_void,
+            plugin: *mut c_void,
+        ) {
+            use ::std::any::Any;
+            use ::std::sync::Arc;
+            use ::std::{panic, process};
+            use $crate::plugins::ffi::{call_config_func, call_register_func_with_state};
+
+            let closure = move || {
+                call_config_func(config_func, plugin, $metadata);
+
+                let state: Arc<dyn Any + Send + Sync> = Arc::new($state);
+                $(
+                    call_register_func_with_state(
+                        register_func,
+                        plugin,
+                        $filter,
+                        Some(state.clone()),
+                    );
+                )*
+            };
+
+            if panic::catch_unwind(closure).is_err() {
+                process::abort();
+            }
+        }
+    );
     ($metadata:expr, [$($filter:expr),*$(,)*]) => (
         use ::std::os::raw::c_void;
 
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use format::{ColorFamily, SampleType};
+use video_info::{Property, VideoInfo};
+
+/// A description of the clip formats a filter accepts.
+///
+/// A default constraint accepts any clip. Restrictions on the format are only checked if the
+/// format is constant; use `constant_format()` to reject clips with a variable format. The same
+/// applies to the resolution.
+///
+/// # Example
+/// ```
+/// # use vapoursynth::format::{ColorFamily, SampleType};
+/// # use vapoursynth::plugins::FormatConstraint;
+/// // Constant YUV or Gray, 8–16-bit integer, mod-2 width.
+/// let constraint = FormatConstraint::new()
+///     .constant_format()
+///     .color_families(&[ColorFamily::YUV, ColorFamily::Gray])
+///     .sample_types(&[SampleType::Integer])
+///     .bits_per_sample(8..=16)
+///     .width_modulo(2);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FormatConstraint {
+    constant_format: bool,
+    constant_resolution: bool,
+    color_families: Option<Vec<ColorFamily>>,
+    sample_types: Option<Vec<SampleType>>,
+    bits_per_sample: Option<RangeInclusive<u8>>,
+    max_sub_sampling: Option<(u8, u8)>,
+    width_modulo: usize,
+    height_modulo: usize,
+}
+
+/// The reason a clip was rejected by a `FormatConstraint`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum FormatConstraintViolation {
+    /// The clip has a variable format.
+    VariableFormat,
+    /// The clip has a variable resolution.
+    VariableResolution,
+    /// The color family of the clip isn't allowed.
+    ColorFamily(ColorFamily),
+    /// The sample type of the clip isn't allowed.
+    SampleType(SampleType),
+    /// The number of bits per sample of the clip isn't allowed.
+    BitsPerSample(u8),
+    /// The subsampling of the clip is too high.
+    SubSampling(u8, u8),
+    /// The width of the clip isn't a multiple of the required value.
+    Width(usize),
+    /// The height of the clip isn't a multiple of the required value.
+    Height(usize),
+}
+
+/// An error indicating that a clip argument doesn't satisfy a `FormatConstraint`.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Argument `{argument}`: {violation} (expected {constraint})")]
+pub struct FormatConstraintError {
+    argument: String,
+    violation: FormatConstraintViolation,
+    constraint: FormatConstraint,
+}
+
+impl FormatConstraintError {
+    /// Returns the name of the offending argument.
+    #[inline]
+    pub fn argument(&self) -> &str {
+        &self.argument
+    }
+
+    /// Returns the reason the clip was rejected.
+    #[inline]
+    pub fn violation(&self) -> FormatConstraintViolation {
+        self.violation
+    }
+}
+
+impl Default for FormatConstraint {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            constant_format: false,
+            constant_resolution: false,
+            color_families: None,
+            sample_types: None,
+            bits_per_sample: None,
+            max_sub_sampling: None,
+            width_modulo: 1,
+            height_modulo: 1,
+        }
+    }
+}
+
+impl FormatConstraint {
+    /// Creates a constraint which accepts any clip.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the clip to have a constant format.
+    #[inline]
+    pub fn constant_format(mut self) -> Self {
+        self.constant_format = true;
+        self
+    }
+
+    /// Requires the clip to have a constant resolution.
+    #[inline]
+    pub fn constant_resolution(mut self) -> Self {
+        self.constant_resolution = true;
+        self
+    }
+
+    /// Restricts the allowed color families.
+    #[inline]
+    pub fn color_families(mut self, color_families: &[ColorFamily]) -> Self {
+        self.color_families = Some(color_families.to_vec());
+        self
+    }
+
+    /// Restricts the allowed sample types.
+    #[inline]
+    pub fn sample_types(mut self, sample_types: &[SampleType]) -> Self {
+        self.sample_types = Some(sample_types.to_vec());
+        self
+    }
+
+    /// Restricts the allowed number of bits per sample.
+    #[inline]
+    pub fn bits_per_sample(mut self, bits_per_sample: RangeInclusive<u8>) -> Self {
+        self.bits_per_sample = Some(bits_per_sample);
+        self
+    }
+
+    /// Restricts the maximum log2 subsampling factors.
+    ///
+    /// For example, `max_sub_sampling(0, 0)` allows only formats without subsampling.
+    #[inline]
+    pub fn max_sub_sampling(mut self, sub_sampling_w: u8, sub_sampling_h: u8) -> Self {
+        self.max_sub_sampling = Some((sub_sampling_w, sub_sampling_h));
+        self
+    }
+
+    /// Requires the width of the clip to be a multiple of `modulo`.
+    ///
+    /// # Panics
+    /// Panics if `modulo` is zero.
+    #[inline]
+    pub fn width_modulo(mut self, modulo: usize) -> Self {
+        assert!(modulo > 0);
+        self.width_modulo = modulo;
+        self
+    }
+
+    /// Requires the height of the clip to be a multiple of `modulo`.
+    ///
+    /// # Panics
+    /// Panics if `modulo` is zero.
+    #[inline]
+    pub fn height_modulo(mut self, modulo: usize) -> Self {
+        assert!(modulo > 0);
+        self.height_modulo = modulo;
+        self
+    }
+
+    /// Checks whether a clip with the given video info satisfies this constraint.
+    ///
+    /// `argument` is the name of the clip argument, used in the error message.
+    pub fn check(&self, argument: &str, info: &VideoInfo) -> Result<(), FormatConstraintError> {
+        self.violation(info).map_or(Ok(()), |violation| {
+            Err(FormatConstraintError {
+                argument: argument.to_owned(),
+                violation,
+                constraint: self.clone(),
+            })
+        })
+    }
+
+    /// Returns the first violation of this constraint by a clip with the given video info.
+    fn violation(&self, info: &VideoInfo) -> Option<FormatConstraintViolation> {
+        match info.format {
+            Property::Variable if self.constant_format => {
+                return Some(FormatConstraintViolation::VariableFormat);
+            }
+            Property::Variable => {}
+            Property::Constant(format) => {
+                let color_family = format.color_family();
+                if let Some(ref color_families) = self.color_families {
+                    if !color_families.contains(&color_family) {
+                        return Some(FormatConstraintViolation::ColorFamily(color_family));
+                    }
+                }
+
+                let sample_type = format.sample_type();
+                if let Some(ref sample_types) = self.sample_types {
+                    if !sample_types.contains(&sample_type) {
+                        return Some(FormatConstraintViolation::SampleType(sample_type));
+                    }
+                }
+
+                let bits_per_sample = format.bits_per_sample();
+                if let Some(ref range) = self.bits_per_sample {
+                    if !range.contains(&bits_per_sample) {
+                        return Some(FormatConstraintViolation::BitsPerSample(bits_per_sample));
+                    }
+                }
+
+                let sub_sampling = (format.sub_sampling_w(), format.sub_sampling_h());
+                if let Some((max_w, max_h)) = self.max_sub_sampling {
+                    if sub_sampling.0 > max_w || sub_sampling.1 > max_h {
+                        return Some(FormatConstraintViolation::SubSampling(
+                            sub_sampling.0,
+                            sub_sampling.1,
+                        ));
+                    }
+                }
+            }
+        }
+
+        match info.resolution {
+            Property::Variable if self.constant_resolution => {
+                Some(FormatConstraintViolation::VariableResolution)
+            }
+            Property::Variable => None,
+            Property::Constant(resolution) => {
+                if resolution.width % self.width_modulo != 0 {
+                    Some(FormatConstraintViolation::Width(resolution.width))
+                } else if resolution.height % self.height_modulo != 0 {
+                    Some(FormatConstraintViolation::Height(resolution.height))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for FormatConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+
+        if self.constant_format {
+            parts.push("constant format".to_owned());
+        }
+        if self.constant_resolution {
+            parts.push("constant resolution".to_owned());
+        }
+        if let Some(ref color_families) = self.color_families {
+            parts.push(format!("{}", DisplayList(color_families)));
+        }
+        if let Some(ref sample_types) = self.sample_types {
+            parts.push(format!("{}", DisplayList(sample_types)));
+        }
+        if let Some(ref range) = self.bits_per_sample {
+            if range.start() == range.end() {
+                parts.push(format!("{}-bit", range.start()));
+            } else {
+                parts.push(format!("{}–{}-bit", range.start(), range.end()));
+            }
+        }
+        if let Some((w, h)) = self.max_sub_sampling {
+            parts.push(format!("subsampling of at most {}×{}", w, h));
+        }
+        if self.width_modulo > 1 {
+            parts.push(format!("mod-{} width", self.width_modulo));
+        }
+        if self.height_modulo > 1 {
+            parts.push(format!("mod-{} height", self.height_modulo));
+        }
+
+        if parts.is_empty() {
+            write!(f, "any clip")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// A helper for displaying lists of allowed values separated by slashes.
+struct DisplayList<'a, T>(&'a [T]);
+
+impl<'a, T: fmt::Display> fmt::Display for DisplayList<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FormatConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatConstraintViolation::VariableFormat => write!(f, "the format is variable"),
+            FormatConstraintViolation::VariableResolution => {
+                write!(f, "the resolution is variable")
+            }
+            FormatConstraintViolation::ColorFamily(x) => {
+                write!(f, "color family {} is not supported", x)
+            }
+            FormatConstraintViolation::SampleType(x) => {
+                write!(f, "sample type {} is not supported", x)
+            }
+            FormatConstraintViolation::BitsPerSample(x) => {
+                write!(f, "{} bits per sample are not supported", x)
+            }
+            FormatConstraintViolation::SubSampling(w, h) => {
+                write!(f, "subsampling {}×{} is not supported", w, h)
+            }
+            FormatConstraintViolation::Width(x) => write!(f, "width {} is not supported", x),
+            FormatConstraintViolation::Height(x) => write!(f, "height {} is not supported", x),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::marker::PhantomData;
-use std::ptr::NonNull;
+use std::os::raw::c_void;
+use std::ptr::{self, NonNull};
 use vapoursynth_sys as ffi;
 
 use api::API;
@@ -8,18 +9,23 @@ use api::API;
 #[derive(Debug, Clone, Copy)]
 pub struct FrameContext<'a> {
     handle: NonNull<ffi::VSFrameContext>,
+    frame_data: NonNull<*mut c_void>,
     _owner: PhantomData<&'a ()>,
 }
 
 impl<'a> FrameContext<'a> {
-    /// Wraps `handle` in a `FrameContext`.
+    /// Wraps `handle` and the `frame_data` pointer of the request in a `FrameContext`.
     ///
     /// # Safety
-    /// The caller must ensure `handle` is valid and API is cached.
+    /// The caller must ensure `handle` and `frame_data` are valid and API is cached.
     #[inline]
-    pub(crate) unsafe fn from_ptr(handle: *mut ffi::VSFrameContext) -> Self {
+    pub(crate) unsafe fn from_ptr(
+        handle: *mut ffi::VSFrameContext,
+        frame_data: *mut *mut c_void,
+    ) -> Self {
         Self {
             handle: NonNull::new_unchecked(handle),
+            frame_data: NonNull::new_unchecked(frame_data),
             _owner: PhantomData,
         }
     }
@@ -38,3 +44,57 @@ impl<'a> FrameContext<'a> {
         index as _
     }
 }
+
+/// Data attached to a frame request, along with the function dropping it.
+struct FrameData {
+    data: *mut c_void,
+    drop: unsafe fn(*mut c_void),
+}
+
+/// Drops a `Box<T>` passed as a raw pointer.
+unsafe fn drop_box<T>(ptr: *mut c_void) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+impl<'a> FrameContext<'a> {
+    /// Attaches `data` to the current frame request, replacing any previous data.
+    ///
+    /// Unlike the frame context pointer, the data is kept by VapourSynth between the calls of a
+    /// request. It's dropped when the request finishes or fails unless taken before.
+    pub(crate) fn set_frame_data<T: Send>(self, data: T) {
+        self.free_frame_data();
+
+        let data = FrameData {
+            data: Box::into_raw(Box::new(data)) as *mut c_void,
+            drop: drop_box::<T>,
+        };
+        unsafe {
+            *self.frame_data.as_ptr() = Box::into_raw(Box::new(data)) as *mut c_void;
+        }
+    }
+
+    /// Takes the data attached to the current frame request.
+    ///
+    /// # Safety
+    /// The caller must ensure the data was attached with `set_frame_data()` as a `T`.
+    pub(crate) unsafe fn take_frame_data<T>(self) -> Option<T> {
+        let ptr = self.frame_data.as_ptr().replace(ptr::null_mut());
+        if ptr.is_null() {
+            return None;
+        }
+
+        let frame_data = Box::from_raw(ptr as *mut FrameData);
+        Some(*Box::from_raw(frame_data.data as *mut T))
+    }
+
+    /// Drops the data attached to the current frame request, if any.
+    pub(crate) fn free_frame_data(self) {
+        unsafe {
+            let ptr = self.frame_data.as_ptr().replace(ptr::null_mut());
+            if !ptr.is_null() {
+                let frame_data = Box::from_raw(ptr as *mut FrameData);
+                (frame_data.drop)(frame_data.data);
+            }
+        }
+    }
+}
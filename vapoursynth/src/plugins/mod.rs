@@ -1,5 +1,7 @@
 //! Things related to making VapourSynth plugins.
-use failure::Error;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::{error, fmt};
 
 use api::API;
 use core::CoreRef;
@@ -9,11 +11,35 @@ use map::{self, Map, Value, ValueIter};
 use node::Node;
 use video_info::VideoInfo;
 
+mod format_constraint;
+pub use self::format_constraint::{
+    FormatConstraint, FormatConstraintError, FormatConstraintViolation,
+};
+
 mod frame_context;
 pub use self::frame_context::FrameContext;
 
+mod multi_output;
+pub use self::multi_output::{MultiOutput, MultiOutputFilter};
+
+mod node_context;
+pub use self::node_context::NodeContext;
+
+pub mod profiling;
+
+pub(crate) mod state;
+pub use self::state::plugin_state;
+
 pub mod ffi;
 
+/// The error type returned from filters and filter functions.
+///
+/// Any error convertible into it can be returned with `?`: types implementing
+/// `std::error::Error` (for example those made with `thiserror`), `anyhow::Error` and strings.
+/// The `source()` chain is included in the error message reported to VapourSynth, and so is the
+/// backtrace of `anyhow` errors.
+pub type Error = Box<dyn error::Error + Send + Sync>;
+
 /// Plugin metadata.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Metadata {
@@ -74,6 +100,22 @@ pub trait FilterFunction: Send + Sync {
     /// `blah:clip;moo:int[]:opt;asdf:float:opt;`
     fn args(&self) -> &str;
 
+    /// Returns the format constraints on the clip arguments.
+    ///
+    /// Each entry is the name of a `clip` argument and the constraint every clip passed in it must
+    /// satisfy. The constraints are checked before calling `create()`; a violating clip makes the
+    /// filter creation fail with a `FormatConstraintError` naming the argument. Absent optional
+    /// arguments are skipped.
+    ///
+    /// Registering the filter function panics if a constraint names an argument which doesn't
+    /// exist or isn't a clip.
+    ///
+    /// The default implementation returns no constraints.
+    #[inline]
+    fn format_constraints(&self) -> &[(&str, FormatConstraint)] {
+        &[]
+    }
+
     /// The callback for this filter function.
     ///
     /// In most cases this is where you should create a new instance of the filter and return it.
@@ -97,15 +139,45 @@ pub trait FilterFunction: Send + Sync {
 }
 
 /// A filter interface.
+///
+/// Panics in `video_info()`, `get_frame_initial()` and `get_frame()` are caught and reported as
+/// filter errors containing the panic message, and so are panics in `FilterFunction::create()`.
+/// Enable the `abort-on-filter-panic` feature to abort the process instead.
 // TODO: perhaps it's possible to figure something out about Send + Sync with specialization? Since
 // there are Node flags which say that the filter will be called strictly by one thread, in which
 // case Sync shouldn't be required.
 pub trait Filter<'core>: Send + Sync {
     /// Returns the parameters of this filter's output node.
     ///
-    /// The returned vector should contain one entry for each node output index.
+    /// The returned vector should contain one entry for each node output index. See
+    /// `MultiOutputFilter` for an easier way to write filters with several outputs.
     fn video_info(&self, api: API, core: CoreRef<'core>) -> Vec<VideoInfo<'core>>;
 
+    /// Initializes the filter once its node has been created.
+    ///
+    /// This is called from the VapourSynth init callback, right after the video info is set.
+    /// Returning an error makes the node creation fail with that error.
+    ///
+    /// The default implementation does nothing.
+    #[inline]
+    fn on_init(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        _context: NodeContext<'_, 'core>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Releases the resources of the filter.
+    ///
+    /// This is called when VapourSynth frees the node, right before the filter is dropped. It's
+    /// also called when the node creation fails in `video_info()` or `on_init()`.
+    ///
+    /// The default implementation does nothing.
+    #[inline]
+    fn on_free(&mut self) {}
+
     /// Requests the necessary frames from downstream nodes.
     ///
     /// This is always the first function to get called for a given frame `n`.
@@ -142,9 +214,20 @@ pub trait Filter<'core>: Send + Sync {
 }
 
 /// An internal trait representing a filter argument type.
-pub trait FilterArgument<'map, 'elem: 'map>: Value<'map, 'elem> + private::Sealed {
+///
+/// Besides the types which can be stored in a map, this is implemented for `bool`, the smaller
+/// integer types, `f32` and `PathBuf`, which are converted from the map values, and for user enums
+/// implementing `EnumArgument`. Values which don't fit into the argument type make the filter
+/// creation fail with `map::Error::ArgumentOutOfRange`.
+pub trait FilterArgument<'map, 'elem: 'map>: Sized + private::Sealed {
+    /// The type of the map value this argument is converted from.
+    type Raw: Value<'map, 'elem>;
+
     /// Returns the VapourSynth type name for this argument type.
     fn type_name() -> &'static str;
+
+    /// Converts the map value of the argument with the given name.
+    fn from_raw(raw: Self::Raw, key: &str) -> map::Result<Self>;
 }
 
 /// An internal trait representing a filter parameter type (argument type + whether it's an array
@@ -160,48 +243,214 @@ pub trait FilterParameter<'map, 'elem: 'map>: private::Sealed {
     fn is_optional() -> bool;
 
     /// Retrieves this parameter from the given map.
-    fn get_from_map(map: &'map Map<'elem>, key: &str) -> Self;
+    ///
+    /// Fails if the argument couldn't be converted, for example if a string isn't valid UTF-8 or
+    /// an integer is out of range.
+    fn get_from_map(map: &'map Map<'elem>, key: &str) -> map::Result<Self>
+    where
+        Self: Sized;
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for i64 {
+/// A user enum which can be used as a filter argument.
+///
+/// The enum is passed to the filter either as an integer (`Repr = i64`) or as a string
+/// (`Repr = str`). Values which don't correspond to any variant make the filter creation fail
+/// with `map::Error::ArgumentOutOfRange`.
+///
+/// # Example
+/// ```
+/// # extern crate vapoursynth;
+/// use vapoursynth::plugins::EnumArgument;
+///
+/// enum Mode {
+///     Fast,
+///     Slow,
+/// }
+///
+/// impl EnumArgument for Mode {
+///     type Repr = str;
+///
+///     fn from_repr(value: &str) -> Option<Self> {
+///         match value {
+///             "fast" => Some(Mode::Fast),
+///             "slow" => Some(Mode::Slow),
+///             _ => None,
+///         }
+///     }
+/// }
+/// # fn main() {}
+/// ```
+pub trait EnumArgument: Sized {
+    /// The type the enum is passed as, `i64` or `str`.
+    type Repr: ?Sized;
+
+    /// Returns the variant corresponding to `value`, or `None` if there's no such variant.
+    fn from_repr(value: &Self::Repr) -> Option<Self>;
+}
+
+/// An internal trait representing a type user enums can be passed as.
+pub trait EnumRepr<'map, 'elem: 'map>: private::Sealed {
+    /// The type of the map value the enum is converted from.
+    type Raw: Value<'map, 'elem> + fmt::Debug;
+
+    /// Returns the VapourSynth type name for this type.
+    fn type_name() -> &'static str;
+
+    /// Returns the map value as this type.
+    fn from_raw(raw: &Self::Raw) -> &Self;
+}
+
+impl<'map, 'elem: 'map> EnumRepr<'map, 'elem> for i64 {
+    type Raw = i64;
+
     #[inline]
     fn type_name() -> &'static str {
         "int"
     }
+
+    #[inline]
+    fn from_raw(raw: &Self::Raw) -> &Self {
+        raw
+    }
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for f64 {
+impl<'map, 'elem: 'map> EnumRepr<'map, 'elem> for str {
+    type Raw = &'map str;
+
     #[inline]
     fn type_name() -> &'static str {
-        "float"
+        "data"
     }
+
+    #[inline]
+    fn from_raw(raw: &Self::Raw) -> &Self {
+        raw
+    }
+}
+
+// Implements FilterArgument for types stored in the map as they are.
+macro_rules! impl_filter_argument {
+    ($type:ty, $type_name:expr) => {
+        impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for $type {
+            type Raw = Self;
+
+            #[inline]
+            fn type_name() -> &'static str {
+                $type_name
+            }
+
+            #[inline]
+            fn from_raw(raw: Self::Raw, _key: &str) -> map::Result<Self> {
+                Ok(raw)
+            }
+        }
+    };
+}
+
+impl_filter_argument!(i64, "int");
+impl_filter_argument!(f64, "float");
+impl_filter_argument!(&'map [u8], "data");
+impl_filter_argument!(&'map str, "data");
+impl_filter_argument!(String, "data");
+impl_filter_argument!(Node<'elem>, "clip");
+impl_filter_argument!(FrameRef<'elem>, "frame");
+impl_filter_argument!(Function<'elem>, "func");
+
+// Implements FilterArgument for integer types converted from i64 with a range check.
+macro_rules! impl_integer_filter_argument {
+    ($type:ty) => {
+        impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for $type {
+            type Raw = i64;
+
+            #[inline]
+            fn type_name() -> &'static str {
+                "int"
+            }
+
+            #[inline]
+            fn from_raw(raw: Self::Raw, key: &str) -> map::Result<Self> {
+                <$type>::try_from(raw).map_err(|_| {
+                    map::Error::out_of_range(
+                        key,
+                        format!("{} is not in {}..={}", raw, <$type>::MIN, <$type>::MAX),
+                    )
+                })
+            }
+        }
+    };
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for &'map [u8] {
+impl_integer_filter_argument!(i32);
+impl_integer_filter_argument!(u32);
+impl_integer_filter_argument!(usize);
+impl_integer_filter_argument!(u8);
+
+impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for bool {
+    type Raw = i64;
+
     #[inline]
     fn type_name() -> &'static str {
-        "data"
+        "int"
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw, _key: &str) -> map::Result<Self> {
+        Ok(raw != 0)
     }
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for Node<'elem> {
+impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for f32 {
+    type Raw = f64;
+
     #[inline]
     fn type_name() -> &'static str {
-        "clip"
+        "float"
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw, key: &str) -> map::Result<Self> {
+        let x = raw as f32;
+        if x.is_infinite() && raw.is_finite() {
+            Err(map::Error::out_of_range(
+                key,
+                format!("{} doesn't fit into a 32-bit float", raw),
+            ))
+        } else {
+            Ok(x)
+        }
     }
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for FrameRef<'elem> {
+impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for PathBuf {
+    type Raw = &'map str;
+
     #[inline]
     fn type_name() -> &'static str {
-        "frame"
+        "data"
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw, _key: &str) -> map::Result<Self> {
+        Ok(PathBuf::from(raw))
     }
 }
 
-impl<'map, 'elem: 'map> FilterArgument<'map, 'elem> for Function<'elem> {
+impl<'map, 'elem: 'map, T> FilterArgument<'map, 'elem> for T
+where
+    T: EnumArgument,
+    T::Repr: EnumRepr<'map, 'elem>,
+{
+    type Raw = <T::Repr as EnumRepr<'map, 'elem>>::Raw;
+
     #[inline]
     fn type_name() -> &'static str {
-        "func"
+        <T::Repr as EnumRepr>::type_name()
+    }
+
+    #[inline]
+    fn from_raw(raw: Self::Raw, key: &str) -> map::Result<Self> {
+        T::from_repr(<T::Repr as EnumRepr>::from_raw(&raw))
+            .ok_or_else(|| map::Error::out_of_range(key, format!("{:?} isn't a valid value", raw)))
     }
 }
 
@@ -222,8 +471,9 @@ where
     }
 
     #[inline]
-    fn get_from_map(map: &'map Map<'elem>, key: &str) -> Self {
-        Self::get_from_map(map, key).unwrap()
+    fn get_from_map(map: &'map Map<'elem>, key: &str) -> map::Result<Self> {
+        let raw = <T::Raw as Value>::get_from_map(map, key)?;
+        T::from_raw(raw, key)
     }
 }
 
@@ -244,18 +494,18 @@ where
     }
 
     #[inline]
-    fn get_from_map(map: &'map Map<'elem>, key: &str) -> Self {
-        match <Self::Argument as Value>::get_from_map(map, key) {
-            Ok(x) => Some(x),
-            Err(map::Error::KeyNotFound) => None,
-            _ => unreachable!(),
+    fn get_from_map(map: &'map Map<'elem>, key: &str) -> map::Result<Self> {
+        match <T::Raw as Value>::get_from_map(map, key) {
+            Ok(raw) => T::from_raw(raw, key).map(Some),
+            Err(map::Error::KeyNotFound) => Ok(None),
+            Err(error) => Err(error),
         }
     }
 }
 
 impl<'map, 'elem: 'map, T> FilterParameter<'map, 'elem> for ValueIter<'map, 'elem, T>
 where
-    T: FilterArgument<'map, 'elem>,
+    T: FilterArgument<'map, 'elem, Raw = T> + Value<'map, 'elem>,
 {
     type Argument = T;
 
@@ -270,14 +520,14 @@ where
     }
 
     #[inline]
-    fn get_from_map(map: &'map Map<'elem>, key: &str) -> Self {
-        <Self::Argument>::get_iter_from_map(map, key).unwrap()
+    fn get_from_map(map: &'map Map<'elem>, key: &str) -> map::Result<Self> {
+        <Self::Argument as Value>::get_iter_from_map(map, key)
     }
 }
 
 impl<'map, 'elem: 'map, T> FilterParameter<'map, 'elem> for Option<ValueIter<'map, 'elem, T>>
 where
-    T: FilterArgument<'map, 'elem>,
+    T: FilterArgument<'map, 'elem, Raw = T> + Value<'map, 'elem>,
 {
     type Argument = T;
 
@@ -292,27 +542,41 @@ where
     }
 
     #[inline]
-    fn get_from_map(map: &'map Map<'elem>, key: &str) -> Self {
+    fn get_from_map(map: &'map Map<'elem>, key: &str) -> map::Result<Self> {
         match <Self::Argument as Value>::get_iter_from_map(map, key) {
-            Ok(x) => Some(x),
-            Err(map::Error::KeyNotFound) => None,
-            _ => unreachable!(),
+            Ok(x) => Ok(Some(x)),
+            Err(map::Error::KeyNotFound) => Ok(None),
+            Err(error) => Err(error),
         }
     }
 }
 
 mod private {
-    use super::{FilterArgument, FrameRef, Function, Node, ValueIter};
+    use std::path::PathBuf;
+
+    use super::{EnumArgument, FilterArgument, FrameRef, Function, Node, ValueIter};
 
     pub trait Sealed {}
 
     impl Sealed for i64 {}
+    impl Sealed for i32 {}
+    impl Sealed for u32 {}
+    impl Sealed for usize {}
+    impl Sealed for u8 {}
+    impl Sealed for bool {}
     impl Sealed for f64 {}
+    impl Sealed for f32 {}
     impl<'map> Sealed for &'map [u8] {}
+    impl Sealed for &str {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl Sealed for PathBuf {}
     impl<'elem> Sealed for Node<'elem> {}
     impl<'elem> Sealed for FrameRef<'elem> {}
     impl<'elem> Sealed for Function<'elem> {}
 
+    impl<T> Sealed for T where T: EnumArgument {}
+
     impl<'map, 'elem: 'map, T> Sealed for Option<T> where T: FilterArgument<'map, 'elem> {}
 
     impl<'map, 'elem: 'map, T> Sealed for ValueIter<'map, 'elem, T> where T: FilterArgument<'map, 'elem> {}
@@ -346,7 +610,9 @@ mod private {
 /// ```
 ///
 /// All VapourSynth-supported types can be used, as well as `Option<T>` for optional parameters and
-/// `ValueIter<T>` for array parameters. Array parameters can be empty.
+/// `ValueIter<T>` for array parameters. Array parameters can be empty. Single parameters can also
+/// be of the types converted from VapourSynth values, like `bool`, `u8` or user enums (see
+/// `FilterArgument`).
 ///
 /// Caveat: the macro doesn't currently allow specifying mutable parameters, so to do that they
 /// have to be reassigned to a mutable variable in the function body. This is mainly a problem for
@@ -354,11 +620,18 @@ mod private {
 ///
 /// Another caveat: underscore lifetimes are required for receiving `ValueIter<T>`.
 ///
+/// Format constraints on clip arguments can be declared in an optional `constraints` block after
+/// the filter name (see `FilterFunction::format_constraints()`).
+///
 /// # Example
 /// ```ignore
 /// make_filter_function! {
 ///     MyFilterFunction, "MyFilter"
 ///
+///     constraints {
+///         array_parameter: FormatConstraint::new().constant_format(),
+///     }
+///
 ///     fn create_my_filter<'core>(
 ///         _api: API,
 ///         _core: CoreRef<'core>,
@@ -378,6 +651,37 @@ macro_rules! make_filter_function {
     (
         $struct_name:ident, $function_name:tt
 
+        $(#[$attr:meta])*
+        fn $create_fn_name:ident<$lifetime:tt>(
+            $api_arg_name:ident : $api_arg_type:ty,
+            $core_arg_name:ident : $core_arg_type:ty,
+            $($arg_name:ident : $arg_type:ty),* $(,)*
+        ) -> $return_type:ty {
+            $($body:tt)*
+        }
+    ) => (
+        $crate::make_filter_function! {
+            $struct_name, $function_name
+
+            constraints {}
+
+            $(#[$attr])*
+            fn $create_fn_name<$lifetime>(
+                $api_arg_name : $api_arg_type,
+                $core_arg_name : $core_arg_type,
+                $($arg_name : $arg_type),*
+            ) -> $return_type {
+                $($body)*
+            }
+        }
+    );
+    (
+        $struct_name:ident, $function_name:tt
+
+        constraints {
+            $($constraint_arg_name:ident : $constraint:expr),* $(,)*
+        }
+
         $(#[$attr:meta])*
         fn $create_fn_name:ident<$lifetime:tt>(
             $api_arg_name:ident : $api_arg_type:ty,
@@ -389,9 +693,12 @@ macro_rules! make_filter_function {
     ) => (
         struct $struct_name {
             args: String,
+            constraints: Vec<(&'static str, $crate::plugins::FormatConstraint)>,
         }
 
         impl $struct_name {
+            // The argument types may refer to 'core.
+            #[allow(clippy::extra_unused_lifetimes)]
             fn new<'core>() -> Self {
                 let mut args = String::new();
 
@@ -399,8 +706,10 @@ macro_rules! make_filter_function {
                     // Don't use format!() for better constant propagation.
                     args += stringify!($arg_name); // TODO: allow using a different name.
                     args += ":";
-                    args
-                        += <<$arg_type as $crate::plugins::FilterParameter>::Argument>::type_name();
+                    args += <
+                        <$arg_type as $crate::plugins::FilterParameter>::Argument
+                        as $crate::plugins::FilterArgument
+                    >::type_name();
 
                     if <$arg_type as $crate::plugins::FilterParameter>::is_array() {
                         args += "[]";
@@ -418,7 +727,11 @@ macro_rules! make_filter_function {
                     args += ";";
                 )*
 
-                Self { args }
+                let constraints = vec![
+                    $((stringify!($constraint_arg_name), $constraint)),*
+                ];
+
+                Self { args, constraints }
             }
         }
 
@@ -433,13 +746,21 @@ macro_rules! make_filter_function {
                 &self.args
             }
 
+            #[inline]
+            fn format_constraints(&self) -> &[(&str, $crate::plugins::FormatConstraint)] {
+                &self.constraints
+            }
+
             #[inline]
             fn create<'core>(
                 &self,
                 api: API,
                 core: CoreRef<'core>,
                 args: &Map<'core>,
-            ) -> Result<Option<Box<$crate::plugins::Filter<'core> + 'core>>, Error> {
+            ) -> Result<
+                Option<Box<dyn $crate::plugins::Filter<'core> + 'core>>,
+                $crate::plugins::Error,
+            > {
                 $create_fn_name(
                     api,
                     core,
@@ -447,7 +768,7 @@ macro_rules! make_filter_function {
                         <$arg_type as $crate::plugins::FilterParameter>::get_from_map(
                             args,
                             stringify!($arg_name),
-                        )
+                        )?
                     ),*
                 )
             }
@@ -0,0 +1,114 @@
+use api::API;
+use core::CoreRef;
+use frame::FrameRef;
+use plugins::{Error, Filter, FrameContext, NodeContext};
+use video_info::VideoInfo;
+
+/// An interface for filters with several outputs.
+///
+/// This is a variant of `Filter` where the frame functions receive the index of the output the
+/// frame is requested from. Wrap the filter into `MultiOutput` to use it where a `Filter` is
+/// expected, for example when returning it from `FilterFunction::create()`. VapourSynth returns
+/// the nodes of all outputs in the `clip` key, in the order of `video_info()`.
+pub trait MultiOutputFilter<'core>: Send + Sync {
+    /// Returns the parameters of the output nodes, one entry for each output.
+    fn video_info(&self, api: API, core: CoreRef<'core>) -> Vec<VideoInfo<'core>>;
+
+    /// Initializes the filter once its nodes have been created.
+    ///
+    /// See `Filter::on_init()`.
+    #[inline]
+    fn on_init(
+        &self,
+        _api: API,
+        _core: CoreRef<'core>,
+        _context: NodeContext<'_, 'core>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Releases the resources of the filter.
+    ///
+    /// See `Filter::on_free()`.
+    #[inline]
+    fn on_free(&mut self) {}
+
+    /// Requests the necessary frames for frame `n` of output `output`.
+    ///
+    /// See `Filter::get_frame_initial()`.
+    fn get_frame_initial(
+        &self,
+        api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        output: usize,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, Error>;
+
+    /// Returns frame `n` of output `output`.
+    ///
+    /// See `Filter::get_frame()`.
+    fn get_frame(
+        &self,
+        api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        output: usize,
+        n: usize,
+    ) -> Result<FrameRef<'core>, Error>;
+}
+
+/// An adapter implementing `Filter` for a `MultiOutputFilter`.
+///
+/// The frame requests are dispatched according to `FrameContext::output_index()`.
+#[derive(Debug, Clone, Copy)]
+pub struct MultiOutput<F>(pub F);
+
+impl<'core, F> Filter<'core> for MultiOutput<F>
+where
+    F: MultiOutputFilter<'core>,
+{
+    #[inline]
+    fn video_info(&self, api: API, core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+        self.0.video_info(api, core)
+    }
+
+    #[inline]
+    fn on_init(
+        &self,
+        api: API,
+        core: CoreRef<'core>,
+        context: NodeContext<'_, 'core>,
+    ) -> Result<(), Error> {
+        self.0.on_init(api, core, context)
+    }
+
+    #[inline]
+    fn on_free(&mut self) {
+        self.0.on_free()
+    }
+
+    #[inline]
+    fn get_frame_initial(
+        &self,
+        api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<Option<FrameRef<'core>>, Error> {
+        let output = context.output_index();
+        self.0.get_frame_initial(api, core, context, output, n)
+    }
+
+    #[inline]
+    fn get_frame(
+        &self,
+        api: API,
+        core: CoreRef<'core>,
+        context: FrameContext,
+        n: usize,
+    ) -> Result<FrameRef<'core>, Error> {
+        let output = context.output_index();
+        self.0.get_frame(api, core, context, output, n)
+    }
+}
@@ -0,0 +1,29 @@
+use map::Map;
+use video_info::VideoInfo;
+
+/// The context of a filter node being initialized, passed to `Filter::on_init()`.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeContext<'a, 'core: 'a> {
+    args: &'a Map<'core>,
+    video_info: &'a [VideoInfo<'core>],
+}
+
+impl<'a, 'core> NodeContext<'a, 'core> {
+    /// Creates a `NodeContext` from the filter arguments and the output video info.
+    #[inline]
+    pub(crate) fn new(args: &'a Map<'core>, video_info: &'a [VideoInfo<'core>]) -> Self {
+        Self { args, video_info }
+    }
+
+    /// Returns the arguments the filter was created with.
+    #[inline]
+    pub fn args(self) -> &'a Map<'core> {
+        self.args
+    }
+
+    /// Returns the video info of the node outputs, as returned from `Filter::video_info()`.
+    #[inline]
+    pub fn video_info(self) -> &'a [VideoInfo<'core>] {
+        self.video_info
+    }
+}
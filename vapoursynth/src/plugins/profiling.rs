@@ -0,0 +1,406 @@
+//! Per-filter profiling of the filters implemented with this crate.
+//!
+//! When profiling is enabled, every call to `Filter::get_frame_initial()` and
+//! `Filter::get_frame()` is counted and timed, and so are the frames allocated by the filter with
+//! `FrameRefMut::copy_of()` and `FrameRefMut::new_uninitialized()` during those calls. The
+//! statistics are aggregated by filter name over all instances and can be retrieved with
+//! `report()`.
+//!
+//! Each plugin has its own copy of the statistics. To profile a plugin loaded into an unmodified
+//! application, set the `VAPOURSYNTH_RS_PROFILE` environment variable to `table` or `json`: this
+//! enables profiling and writes the report in that format to the standard error stream.
+//!
+//! The report is written once, by a handler registered with `atexit()`. It runs when the process
+//! exits or, if the plugin is unloaded before, when the plugin is unloaded (VapourSynth unloads
+//! plugins when the core is freed). Filter instances freed earlier, for example temporary ones
+//! created and freed by a script, don't trigger a report of their own.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{self, Write};
+use std::io::{self, Write as IoWrite};
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+use std::{panic, ptr};
+
+/// The environment variable enabling profiling and the report dump.
+pub const ENV_VARIABLE: &str = "VAPOURSYNTH_RS_PROFILE";
+
+/// Formats of the profiling report.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DumpFormat {
+    /// A human-readable table, see the `Display` implementation of `Report`.
+    Table,
+    /// JSON, see `Report::to_json()`.
+    Json,
+}
+
+/// The statistics of a filter.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct FilterStats {
+    /// The name of the filter.
+    pub name: String,
+    /// The number of instances with profiled calls.
+    pub instances: u64,
+    /// The number of `Filter::get_frame_initial()` calls.
+    pub initial_calls: u64,
+    /// The time spent in `Filter::get_frame_initial()`.
+    pub initial_time: Duration,
+    /// The number of `Filter::get_frame()` calls.
+    pub calls: u64,
+    /// The time spent in `Filter::get_frame()`.
+    pub time: Duration,
+    /// The number of frames allocated in the frame functions.
+    pub frames_allocated: u64,
+}
+
+impl FilterStats {
+    /// Returns the time spent in both frame functions.
+    #[inline]
+    pub fn total_time(&self) -> Duration {
+        self.initial_time + self.time
+    }
+}
+
+/// A profiling report.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Report {
+    filters: Vec<FilterStats>,
+}
+
+impl Report {
+    /// Creates a report, sorting the filters by the total time spent in them.
+    pub(crate) fn new(mut filters: Vec<FilterStats>) -> Self {
+        filters.sort_by(|a, b| {
+            b.total_time()
+                .cmp(&a.total_time())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Self { filters }
+    }
+
+    /// Returns the statistics of the filters, slowest first.
+    #[inline]
+    pub fn filters(&self) -> &[FilterStats] {
+        &self.filters
+    }
+
+    /// Returns the report as JSON.
+    ///
+    /// The JSON is an object with a `filters` array, containing an object with the fields of
+    /// `FilterStats` for each filter. The times are given in nanoseconds, in the `initial_time_ns`
+    /// and `time_ns` fields.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"filters\":[");
+
+        for (i, stats) in self.filters.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+
+            json.push_str("{\"name\":");
+            push_json_string(&mut json, &stats.name);
+            write!(
+                json,
+                ",\"instances\":{},\"initial_calls\":{},\"initial_time_ns\":{},\"calls\":{},\
+                 \"time_ns\":{},\"frames_allocated\":{}}}",
+                stats.instances,
+                stats.initial_calls,
+                stats.initial_time.as_nanos(),
+                stats.calls,
+                stats.time.as_nanos(),
+                stats.frames_allocated,
+            )
+            .unwrap();
+        }
+
+        json.push_str("]}");
+        json
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .filters
+            .iter()
+            .map(|stats| stats.name.chars().count())
+            .chain(Some("Filter".len()))
+            .max()
+            .unwrap();
+
+        writeln!(
+            f,
+            "{:<width$} {:>9} {:>9} {:>12} {:>9} {:>12} {:>9}",
+            "Filter",
+            "Instances",
+            "Initial",
+            "Initial (ms)",
+            "Frames",
+            "Frames (ms)",
+            "Allocated",
+            width = width
+        )?;
+
+        for stats in &self.filters {
+            writeln!(
+                f,
+                "{:<width$} {:>9} {:>9} {:>12.3} {:>9} {:>12.3} {:>9}",
+                stats.name,
+                stats.instances,
+                stats.initial_calls,
+                milliseconds(stats.initial_time),
+                stats.calls,
+                milliseconds(stats.time),
+                stats.frames_allocated,
+                width = width
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts a duration into fractional milliseconds.
+#[inline]
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+/// Pushes `x` as a JSON string literal.
+pub(crate) fn push_json_string(json: &mut String, x: &str) {
+    json.push('"');
+    for c in x.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// The statistics of a filter, shared by its instances.
+#[derive(Debug, Default)]
+pub(crate) struct Entry {
+    name: String,
+    instances: AtomicU64,
+    initial_calls: AtomicU64,
+    initial_nanos: AtomicU64,
+    calls: AtomicU64,
+    nanos: AtomicU64,
+    frames_allocated: AtomicU64,
+}
+
+impl Entry {
+    /// Returns a snapshot of the statistics.
+    fn stats(&self) -> FilterStats {
+        FilterStats {
+            name: self.name.clone(),
+            instances: self.instances.load(Ordering::Relaxed),
+            initial_calls: self.initial_calls.load(Ordering::Relaxed),
+            initial_time: Duration::from_nanos(self.initial_nanos.load(Ordering::Relaxed)),
+            calls: self.calls.load(Ordering::Relaxed),
+            time: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            frames_allocated: self.frames_allocated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the statistics, except for the instance count.
+    fn reset(&self) {
+        for counter in &[
+            &self.initial_calls,
+            &self.initial_nanos,
+            &self.calls,
+            &self.nanos,
+            &self.frames_allocated,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+lazy_static! {
+    /// The statistics of all filters by name.
+    static ref ENTRIES: Mutex<HashMap<String, Arc<Entry>>> = Mutex::new(HashMap::new());
+
+    /// The format of the report to write on shutdown.
+    static ref DUMP_FORMAT: Mutex<Option<DumpFormat>> = Mutex::new(None);
+}
+
+/// Whether profiling is enabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The entry of the filter whose frame function is running on this thread.
+    static CURRENT: Cell<*const Entry> = const { Cell::new(ptr::null()) };
+}
+
+/// Enables or disables profiling.
+///
+/// Filters created while profiling was disabled are profiled too once it's enabled.
+#[inline]
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns `true` if profiling is enabled.
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+/// Sets the format of the report written to the standard error stream on shutdown, or disables
+/// writing it with `None`.
+///
+/// The report is written once, when the process exits or the plugin is unloaded, see the module
+/// documentation.
+pub fn dump_on_shutdown(format: Option<DumpFormat>) {
+    static REGISTER: Once = Once::new();
+
+    *DUMP_FORMAT.lock().unwrap() = format;
+
+    if format.is_some() {
+        REGISTER.call_once(|| unsafe {
+            atexit(dump);
+        });
+    }
+}
+
+/// Writes the report in the format set with `dump_on_shutdown()`.
+extern "C" fn dump() {
+    // Unwinding out of an `atexit()` handler is undefined behavior.
+    let _ = panic::catch_unwind(|| {
+        let format = *DUMP_FORMAT.lock().unwrap();
+        let mut stderr = io::stderr();
+        let _ = match format {
+            Some(DumpFormat::Table) => write!(stderr, "{}", report()),
+            Some(DumpFormat::Json) => writeln!(stderr, "{}", report().to_json()),
+            None => Ok(()),
+        };
+    });
+}
+
+/// Returns the statistics of all filters created so far.
+pub fn report() -> Report {
+    let entries = ENTRIES.lock().unwrap();
+    Report::new(entries.values().map(|entry| entry.stats()).collect())
+}
+
+/// Resets the call, time and allocation statistics of all filters.
+pub fn reset() {
+    for entry in ENTRIES.lock().unwrap().values() {
+        entry.reset();
+    }
+}
+
+/// Applies the `VAPOURSYNTH_RS_PROFILE` environment variable.
+#[inline]
+pub(crate) fn init_from_env() {
+    static ONCE: Once = Once::new();
+
+    ONCE.call_once(|| {
+        let format = match env::var(ENV_VARIABLE) {
+            Ok(ref x) if x.eq_ignore_ascii_case("table") => DumpFormat::Table,
+            Ok(ref x) if x.eq_ignore_ascii_case("json") => DumpFormat::Json,
+            _ => return,
+        };
+
+        set_enabled(true);
+        dump_on_shutdown(Some(format));
+    });
+}
+
+/// Returns the entry of the filter named `name`, counting a new instance.
+///
+/// Filter instances call this on their first profiled call rather than on creation, so that
+/// creating filters with profiling disabled doesn't touch the statistics.
+pub(crate) fn register(name: &str) -> Arc<Entry> {
+    let entry = {
+        let mut entries = ENTRIES.lock().unwrap();
+        match entries.get(name) {
+            Some(entry) => entry.clone(),
+            None => {
+                let entry = Arc::new(Entry {
+                    name: name.to_owned(),
+                    ..Entry::default()
+                });
+                entries.insert(name.to_owned(), entry.clone());
+                entry
+            }
+        }
+    };
+
+    entry.instances.fetch_add(1, Ordering::Relaxed);
+    entry
+}
+
+/// Counts a frame allocation towards the filter whose frame function is running on this thread.
+#[inline]
+pub(crate) fn record_frame_allocation() {
+    // Frames can be allocated during thread-local destruction.
+    let _ = CURRENT.try_with(|current| {
+        if let Some(entry) = unsafe { current.get().as_ref() } {
+            entry.frames_allocated.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Times a call to a frame function, while attributing frame allocations to its filter.
+pub(crate) struct CallTimer<'a> {
+    entry: &'a Entry,
+    initial: bool,
+    start: Instant,
+    previous: *const Entry,
+}
+
+impl<'a> CallTimer<'a> {
+    /// Starts timing a call to `get_frame_initial()` if `initial` is `true` or to `get_frame()`
+    /// otherwise, returning `None` if profiling is disabled.
+    ///
+    /// `entry` is only called if profiling is enabled.
+    #[inline]
+    pub(crate) fn start<F: FnOnce() -> &'a Entry>(entry: F, initial: bool) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+
+        let entry = entry();
+        let previous = CURRENT.with(|current| current.replace(entry));
+
+        Some(Self {
+            entry,
+            initial,
+            start: Instant::now(),
+            previous,
+        })
+    }
+}
+
+impl<'a> Drop for CallTimer<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        let nanos = self.start.elapsed().as_nanos() as u64;
+        let (calls, total) = if self.initial {
+            (&self.entry.initial_calls, &self.entry.initial_nanos)
+        } else {
+            (&self.entry.calls, &self.entry.nanos)
+        };
+        calls.fetch_add(1, Ordering::Relaxed);
+        total.fetch_add(nanos, Ordering::Relaxed);
+
+        let previous = self.previous;
+        let _ = CURRENT.try_with(|current| current.set(previous));
+    }
+}
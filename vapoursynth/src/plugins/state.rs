@@ -0,0 +1,47 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// A type-erased plugin state.
+pub(crate) type AnyState = Arc<dyn Any + Send + Sync>;
+
+thread_local! {
+    // The state of the plugin whose filter function is being called on this thread.
+    static CURRENT_STATE: RefCell<Option<AnyState>> = RefCell::new(None);
+}
+
+/// Returns the shared state of the plugin whose filter function is being called.
+///
+/// The state is set with the `state` parameter of `export_vapoursynth_plugin!` and is available
+/// from `FilterFunction::create()`. Filters which need the state afterwards should store the
+/// returned `Arc`.
+///
+/// Returns `None` if called outside of `FilterFunction::create()`, if the plugin has no state or
+/// if its type isn't `S`.
+#[inline]
+pub fn plugin_state<S: Any + Send + Sync>() -> Option<Arc<S>> {
+    current_state().and_then(|state| state.downcast::<S>().ok())
+}
+
+/// Returns the type-erased state of the plugin whose filter function is being called.
+#[inline]
+pub(crate) fn current_state() -> Option<AnyState> {
+    CURRENT_STATE.with(|current| current.borrow().clone())
+}
+
+/// Calls `f` with `state` as the current plugin state.
+pub(crate) fn with_state<R>(state: Option<&AnyState>, f: impl FnOnce() -> R) -> R {
+    // Restores the previous state, also on panic.
+    struct Restore(Option<AnyState>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_STATE.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT_STATE.with(|current| current.replace(state.cloned()));
+    let _restore = Restore(previous);
+    f()
+}
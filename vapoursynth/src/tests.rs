@@ -7,14 +7,18 @@ use super::*;
     any(feature = "vapoursynth-functions", feature = "gte-vsscript-api-32")
 ))]
 mod need_api_and_vsscript {
+    use std::error::Error;
     use std::ffi::CStr;
     use std::fmt::Debug;
     use std::mem;
     use std::slice;
     use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     use super::*;
     use function::Function;
+    use node::{CacheStats, CachedNode};
     use prelude::*;
     use video_info::{Framerate, Resolution};
 
@@ -571,8 +575,8 @@ mod need_api_and_vsscript {
 
         #[cfg(feature = "gte-vapoursynth-api-36")]
         {
-            assert_eq!(core.set_max_cache_size(1337), 1337);
-            assert_eq!(core.set_thread_count(3), 3);
+            assert_eq!(core.set_max_cache_size(1337), Ok(1337));
+            assert_eq!(core.set_thread_count(3), Ok(3));
             assert_eq!(core.info().max_framebuffer_size, 1337);
             assert_eq!(core.info().num_threads, 3);
         }
@@ -676,6 +680,641 @@ mod need_api_and_vsscript {
         assert!(out.error().is_none());
         assert_eq!(out.get_int("there").unwrap(), 42);
     }
+
+    #[test]
+    fn functions_typed() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        let core = env.get_core().unwrap();
+        let api = API::get().unwrap();
+
+        let function = Function::from_typed(
+            api,
+            core,
+            &["a", "b", "c"],
+            |a: i64, b: Option<i64>, c: Vec<String>| -> Result<i64, String> {
+                if a < 0 {
+                    return Err("a is negative".to_owned());
+                }
+                Ok(a + b.unwrap_or(0) + c.len() as i64)
+            },
+        );
+
+        let mut in_ = OwnedMap::new(api);
+        in_.set_int("a", 10).unwrap();
+        in_.set_str("c", "x").unwrap();
+        in_.append_str("c", "y").unwrap();
+        let out = function.call_typed(&in_).unwrap();
+        assert_eq!(out.get_int("val"), Ok(12));
+
+        in_.set_int("b", 5).unwrap();
+        let out = function.call_typed(&in_).unwrap();
+        assert_eq!(out.get_int("val"), Ok(17));
+
+        in_.set_int("a", -1).unwrap();
+        let error = function.call_typed(&in_).unwrap_err();
+        assert_eq!(error.message(), "a is negative");
+
+        let error = function.call_typed(&OwnedMap::new(api)).unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Argument `a`: The requested key wasn't found in the map"
+        );
+
+        fn passthrough(clip: Node) -> Result<Node, String> {
+            Ok(clip)
+        }
+        let function = Function::from_typed(api, core, &["clip"], passthrough);
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let mut in_ = OwnedMap::new(api);
+        in_.set_node("clip", &node).unwrap();
+        let out = function.call_typed(&in_).unwrap();
+        assert!(out.get_node("clip").is_ok());
+    }
+
+    #[test]
+    fn frame_copy_on_write() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let (node, _) = (env.get_output(0).unwrap(), None::<Node>);
+
+        let core = env.get_core().unwrap();
+        let frame = node.get_frame(0).unwrap();
+        let original = frame.clone();
+
+        let mut frame = frame.into_mut(core);
+        frame.plane_row_mut::<u8>(0, 0)[0] = 42;
+        assert_eq!(frame.plane_row::<u8>(0, 0)[0], 42);
+        assert_eq!(original.plane_row::<u8>(0, 0)[0], 0);
+
+        let mut frame: FrameRef = frame.into();
+        frame.make_mut(core).plane_row_mut::<u8>(0, 0)[1] = 43;
+        assert_eq!(frame.plane_row::<u8>(0, 0)[..2], [42, 43]);
+        green_frame_test(&original);
+
+        let frame = original
+            .clone()
+            .with_props_mut(core, |props| props.set_int("Tagged", 1))
+            .unwrap();
+        assert_eq!(frame.props().get_int("Tagged"), Ok(1));
+        assert_eq!(
+            original.props().get_int("Tagged"),
+            Err(map::Error::KeyNotFound)
+        );
+        green_frame_test(&frame);
+    }
+
+    #[test]
+    fn map_frames() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let (node, _) = (env.get_output(0).unwrap(), None::<Node>);
+
+        let core = env.get_core().unwrap();
+
+        let inverted = node
+            .map_frames(core, move |_, frame| {
+                let mut frame = frame.into_mut(core);
+                for plane in 0..frame.format().plane_count() {
+                    for row in 0..frame.height(plane) {
+                        for pixel in frame.plane_row_mut::<u8>(plane, row) {
+                            *pixel = 255 - *pixel;
+                        }
+                    }
+                }
+                Ok(frame.into())
+            })
+            .unwrap();
+        assert_eq!(inverted.info().resolution, node.info().resolution);
+
+        let frame = inverted.get_frame(0).unwrap();
+        assert_eq!(frame.plane_row::<u8>(0, 0)[0], 255);
+        assert_eq!(frame.plane_row::<u8>(1, 0)[0], 0);
+        green_frame_test(&node.get_frame(0).unwrap());
+
+        let tagged = node
+            .map_props(core, |n, props| {
+                props.set_int("FrameNumber", n as i64)?;
+                Ok(())
+            })
+            .unwrap();
+        let frame = tagged.get_frame(5).unwrap();
+        assert_eq!(frame.props().get_int("FrameNumber"), Ok(5));
+        green_frame_test(&frame);
+
+        let failing = node
+            .map_frames(core, |_, _| {
+                Err(anyhow::Error::msg("oh no")
+                    .context("while inverting")
+                    .into())
+            })
+            .unwrap();
+        let error = failing.get_frame(3).unwrap_err();
+        assert_eq!(error.frame_number(), 3);
+        assert_eq!(error.node_id(), failing.id());
+        assert_ne!(error.node_id(), node.id());
+        assert_eq!(error.message(), "while inverting");
+        assert_eq!(error.to_string(), "Couldn't get frame 3: while inverting");
+        let cause = error.filter_error().unwrap();
+        assert_eq!(cause.message(), "oh no");
+        assert!(cause.cause().is_none());
+        assert_eq!(
+            error.source().map(ToString::to_string),
+            Some("oh no".to_owned())
+        );
+
+        #[cfg(not(feature = "abort-on-filter-panic"))]
+        {
+            let panicking = node.map_frames(core, |_, _| panic!("boom")).unwrap();
+            let error = panicking.get_frame(0).unwrap_err();
+            assert_eq!(error.message(), "Panic in Filter::get_frame(): boom");
+
+            // The filter keeps working after a panic.
+            assert!(panicking.get_frame(1).is_err());
+        }
+    }
+
+    #[test]
+    fn frame_eval_and_modify_frame() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let (node, _) = (env.get_output(0).unwrap(), None::<Node>);
+
+        let core = env.get_core().unwrap();
+
+        let tagged = node
+            .map_props(core, |_, props| {
+                props.set_int("Tagged", 1)?;
+                Ok(())
+            })
+            .unwrap();
+
+        let selected = {
+            let (even, odd) = (node.clone(), tagged.clone());
+            node.frame_eval(core, move |n| {
+                Ok(if n % 2 == 0 {
+                    even.clone()
+                } else {
+                    odd.clone()
+                })
+            })
+            .unwrap()
+        };
+        for n in 0..4 {
+            let frame = selected.get_frame(n).unwrap();
+            let expected = if n % 2 == 0 {
+                Err(map::Error::KeyNotFound)
+            } else {
+                Ok(1)
+            };
+            assert_eq!(frame.props().get_int("Tagged"), expected);
+            green_frame_test(&frame);
+        }
+
+        let modified = node
+            .modify_frame(core, &[node.clone(), tagged.clone()], move |_, frames| {
+                assert_eq!(frames.len(), 2);
+                let tag = frames[1].props().get_int("Tagged")?;
+                Ok(frames[0]
+                    .clone()
+                    .with_props_mut(core, |props| props.set_int("CopiedTag", tag))?)
+            })
+            .unwrap();
+        let frame = modified.get_frame(3).unwrap();
+        assert_eq!(frame.props().get_int("CopiedTag"), Ok(1));
+        green_frame_test(&frame);
+    }
+
+    #[test]
+    fn filter_lifecycle() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use core::{CoreRef, CreateFilterError};
+        use plugins::{Error, Filter, FrameContext, NodeContext};
+        use video_info::VideoInfo;
+
+        struct Lifecycle<'core> {
+            source: Node<'core>,
+            fail_init: bool,
+            outputs: Arc<AtomicUsize>,
+            freed: Arc<AtomicBool>,
+        }
+
+        impl<'core> Filter<'core> for Lifecycle<'core> {
+            fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+                vec![self.source.info()]
+            }
+
+            fn on_init(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: NodeContext<'_, 'core>,
+            ) -> Result<(), Error> {
+                if self.fail_init {
+                    return Err("no resources".into());
+                }
+                self.outputs
+                    .store(context.video_info().len(), Ordering::SeqCst);
+                Ok(())
+            }
+
+            fn on_free(&mut self) {
+                self.freed.store(true, Ordering::SeqCst);
+            }
+
+            fn get_frame_initial(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: FrameContext,
+                n: usize,
+            ) -> Result<Option<FrameRef<'core>>, Error> {
+                self.source.request_frame_filter(context, n);
+                Ok(None)
+            }
+
+            fn get_frame(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: FrameContext,
+                n: usize,
+            ) -> Result<FrameRef<'core>, Error> {
+                self.source
+                    .get_frame_filter(context, n)
+                    .ok_or_else(|| "Couldn't get the source frame".into())
+            }
+        }
+
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let core = env.get_core().unwrap();
+
+        let outputs = Arc::new(AtomicUsize::new(0));
+        let freed = Arc::new(AtomicBool::new(false));
+        let filter = Lifecycle {
+            source: node.clone(),
+            fail_init: false,
+            outputs: outputs.clone(),
+            freed: freed.clone(),
+        };
+        let lifecycle = core.create_filter("Lifecycle", Box::new(filter)).unwrap();
+        assert_eq!(outputs.load(Ordering::SeqCst), 1);
+        green_frame_test(&lifecycle.get_frame(0).unwrap());
+        assert!(!freed.load(Ordering::SeqCst));
+
+        drop(lifecycle);
+        assert!(freed.load(Ordering::SeqCst));
+
+        let filter = Lifecycle {
+            source: node,
+            fail_init: true,
+            outputs: outputs.clone(),
+            freed: freed.clone(),
+        };
+        match core.create_filter("Lifecycle", Box::new(filter)) {
+            Err(CreateFilterError::Failed(message)) => {
+                assert!(message.contains("no resources"), "{}", message)
+            }
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        };
+    }
+
+    #[test]
+    fn temporal_requests() {
+        use core::CoreRef;
+        use node::EdgeMode;
+        use plugins::{Error, Filter, FrameContext};
+        use video_info::VideoInfo;
+
+        // Outputs the frame numbers of the window around every frame.
+        struct Window<'core> {
+            clips: Vec<Node<'core>>,
+            radius: usize,
+            edge: EdgeMode,
+        }
+
+        impl<'core> Filter<'core> for Window<'core> {
+            fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+                vec![self.clips[0].info()]
+            }
+
+            fn get_frame_initial(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: FrameContext,
+                n: usize,
+            ) -> Result<Option<FrameRef<'core>>, Error> {
+                Node::request_range_filter_multi(&self.clips, context, n, self.radius, self.edge);
+                Ok(None)
+            }
+
+            fn get_frame(
+                &self,
+                _api: API,
+                core: CoreRef<'core>,
+                context: FrameContext,
+                n: usize,
+            ) -> Result<FrameRef<'core>, Error> {
+                let windows =
+                    Node::get_frames_filter_multi(&self.clips, context, n, self.radius, self.edge)
+                        .ok_or("Couldn't get the source frames")?;
+
+                let frame = windows[0][self.radius].clone();
+                frame.with_props_mut(core, |props| -> Result<(), Error> {
+                    for (i, window) in windows.iter().enumerate() {
+                        let key = format!("Window{}", i);
+                        assert_eq!(window.len(), 2 * self.radius + 1);
+                        for frame in window {
+                            props.append_int(&key, frame.props().get_int("FrameNumber")?)?;
+                        }
+                    }
+                    Ok(())
+                })
+            }
+        }
+
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let core = env.get_core().unwrap();
+
+        let tagged = node
+            .map_props(core, |n, props| {
+                props.set_int("FrameNumber", n as i64)?;
+                Ok(())
+            })
+            .unwrap();
+
+        let window = |edge| {
+            let filter = Window {
+                clips: vec![tagged.clone()],
+                radius: 2,
+                edge,
+            };
+            core.create_filter("Window", Box::new(filter)).unwrap()
+        };
+
+        let window_at = |node: &Node, n, key| {
+            let frame = node.get_frame(n).unwrap();
+            let numbers = frame.props().get_int_iter(key).unwrap().collect::<Vec<_>>();
+            numbers
+        };
+
+        let clamped = window(EdgeMode::Clamp);
+        assert_eq!(window_at(&clamped, 0, "Window0"), [0, 0, 0, 1, 2]);
+        assert_eq!(window_at(&clamped, 50, "Window0"), [48, 49, 50, 51, 52]);
+        assert_eq!(window_at(&clamped, 99, "Window0"), [97, 98, 99, 99, 99]);
+
+        let mirrored = window(EdgeMode::Mirror);
+        assert_eq!(window_at(&mirrored, 0, "Window0"), [2, 1, 0, 1, 2]);
+        assert_eq!(window_at(&mirrored, 1, "Window0"), [1, 0, 1, 2, 3]);
+        assert_eq!(window_at(&mirrored, 99, "Window0"), [97, 98, 99, 98, 97]);
+
+        let filter = Window {
+            clips: vec![
+                tagged.clone(),
+                tagged.map_frames(core, |_, f| Ok(f)).unwrap(),
+            ],
+            radius: 1,
+            edge: EdgeMode::Mirror,
+        };
+        let multi = core.create_filter("Window", Box::new(filter)).unwrap();
+        assert_eq!(window_at(&multi, 0, "Window0"), [1, 0, 1]);
+        assert_eq!(window_at(&multi, 0, "Window1"), [1, 0, 1]);
+    }
+
+    #[test]
+    fn multi_output_filter() {
+        use core::CoreRef;
+        use plugins::{Error, FrameContext, MultiOutput, MultiOutputFilter, NodeContext};
+        use video_info::VideoInfo;
+
+        // Outputs the clip tagged with the output index, and the clip at half the length.
+        struct Split<'core> {
+            source: Node<'core>,
+        }
+
+        impl<'core> MultiOutputFilter<'core> for Split<'core> {
+            fn video_info(&self, _api: API, _core: CoreRef<'core>) -> Vec<VideoInfo<'core>> {
+                // The green clip has 100 frames.
+                let info = self.source.info();
+                let half = info.to_builder().num_frames(50).build().unwrap();
+                vec![info, half]
+            }
+
+            fn on_init(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: NodeContext<'_, 'core>,
+            ) -> Result<(), Error> {
+                assert_eq!(context.video_info().len(), 2);
+                Ok(())
+            }
+
+            fn get_frame_initial(
+                &self,
+                _api: API,
+                _core: CoreRef<'core>,
+                context: FrameContext,
+                output: usize,
+                n: usize,
+            ) -> Result<Option<FrameRef<'core>>, Error> {
+                self.source.request_frame_filter(context, n * (output + 1));
+                Ok(None)
+            }
+
+            fn get_frame(
+                &self,
+                _api: API,
+                core: CoreRef<'core>,
+                context: FrameContext,
+                output: usize,
+                n: usize,
+            ) -> Result<FrameRef<'core>, Error> {
+                let frame = self
+                    .source
+                    .get_frame_filter(context, n * (output + 1))
+                    .ok_or("Couldn't get the source frame")?;
+                frame.with_props_mut(core, |props| {
+                    props.set_int("Output", output as i64)?;
+                    props.set_int("SourceFrame", (n * (output + 1)) as i64)?;
+                    Ok(())
+                })
+            }
+        }
+
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let core = env.get_core().unwrap();
+
+        let filter = MultiOutput(Split {
+            source: node.clone(),
+        });
+        let outputs = core.create_filter_multi("Split", Box::new(filter)).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].info().duration(), node.info().duration());
+        assert_eq!(
+            outputs[1].info().duration(),
+            node.info().duration().map(|x| x / 2)
+        );
+
+        for (output, node) in outputs.iter().enumerate() {
+            let frame = node.get_frame(10).unwrap();
+            assert_eq!(frame.props().get_int("Output"), Ok(output as i64));
+            assert_eq!(
+                frame.props().get_int("SourceFrame"),
+                Ok(10 * (output as i64 + 1))
+            );
+            green_frame_test(&frame);
+        }
+
+        let filter = MultiOutput(Split { source: node });
+        let first = core.create_filter("Split", Box::new(filter)).unwrap();
+        assert_eq!(
+            first.get_frame(10).unwrap().props().get_int("Output"),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn graph_of_adaptors() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let core = env.get_core().unwrap();
+
+        graph::set_enabled(true);
+        let frames = node.map_frames(core, |_, frame| Ok(frame)).unwrap();
+        let props = frames.map_props(core, |_, _: &mut Map| Ok(())).unwrap();
+
+        let graph = graph::Graph::new(&props);
+        let names = graph
+            .nodes()
+            .iter()
+            .map(|node| node.filter.as_ref().map(|filter| &filter.name[..]))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("MapProps"), Some("MapFrames"), None]);
+        assert_eq!(graph.nodes()[2].id, node.id());
+
+        let dot = graph::export(&props, graph::Format::Dot);
+        assert!(dot.contains("n1 -> n0 [label=\"clip\"];"));
+        assert!(dot.contains("n2 -> n1 [label=\"clip\"];"));
+    }
+
+    #[test]
+    fn cached_node() {
+        let env =
+            vsscript::Environment::from_file("test-vpy/green.vpy", vsscript::EvalFlags::Nothing)
+                .unwrap();
+
+        #[cfg(feature = "gte-vsscript-api-31")]
+        let (node, _) = env.get_output(0).unwrap();
+        #[cfg(not(feature = "gte-vsscript-api-31"))]
+        let node = env.get_output(0).unwrap();
+
+        let frame = node.get_frame(0).unwrap();
+        let size = (0..3)
+            .map(|plane| frame.stride(plane) * frame.height(plane))
+            .sum::<usize>();
+
+        // Room for two frames.
+        let cache = CachedNode::new(node.clone(), size * 5 / 2).with_prefetch(0);
+        assert_eq!(cache.budget(), size * 5 / 2);
+
+        green_frame_test(&cache.get_frame(0).unwrap());
+        cache.get_frame(1).unwrap();
+        cache.get_frame(0).unwrap();
+        cache.get_frame(2).unwrap();
+        assert!(cache.contains(0));
+        assert!(!cache.contains(1));
+        assert!(cache.contains(2));
+        assert!(cache.get_frame(100).is_err());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.prefetched, 0);
+        assert_eq!(stats.evicted, 1);
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.bytes, size * 2);
+        assert_eq!(stats.hit_ratio(), 0.2);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats().misses, 0);
+        assert_eq!(cache.stats().frames, 2);
+        cache.clear();
+        assert_eq!(cache.stats(), CacheStats::default());
+
+        // Two equal steps backwards start the prefetching.
+        let cache = CachedNode::new(node, size * 10).with_prefetch(2);
+        for &n in &[20, 18, 16] {
+            cache.get_frame(n).unwrap();
+        }
+        assert_eq!(cache.stats().prefetched, 2);
+
+        let start = Instant::now();
+        while !(cache.contains(14) && cache.contains(12)) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        green_frame_test(&cache.get_frame(14).unwrap());
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 3);
+        assert_eq!(cache.stats().prefetched, 3);
+    }
 }
 
 // We need either VSScript API 3.2 or the VapourSynth functions.
@@ -689,7 +1328,12 @@ mod need_api {
     use std::sync::Mutex;
 
     use super::*;
+    use format::FormatBuilder;
+    use function::Function;
+    use node::Flags;
+    use plugins::{FormatConstraint, FormatConstraintViolation};
     use prelude::*;
+    use video_info::{Resolution, VideoInfo};
 
     #[test]
     fn maps() {
@@ -769,51 +1413,409 @@ mod need_api {
             assert_eq!(iter.next(), None);
         }
 
-        #[cfg(feature = "gte-vapoursynth-api-31")]
-        {
-            assert_eq!(map.get_float_array("f"), Ok(&[42f64, 43f64][..]));
-
-            assert_eq!(map.set_float_array("fa", &[10f64, 20f64, 30f64]), Ok(()));
-            assert_eq!(map.get_float_array("fa"), Ok(&[10f64, 20f64, 30f64][..]));
+        #[cfg(feature = "gte-vapoursynth-api-31")]
+        {
+            assert_eq!(map.get_float_array("f"), Ok(&[42f64, 43f64][..]));
+
+            assert_eq!(map.set_float_array("fa", &[10f64, 20f64, 30f64]), Ok(()));
+            assert_eq!(map.get_float_array("fa"), Ok(&[10f64, 20f64, 30f64][..]));
+        }
+
+        assert_eq!(map.set_data("d", &[1, 2, 3]), Ok(()));
+        assert_eq!(map.get_data("d"), Ok(&[1, 2, 3][..]));
+        assert_eq!(map.append_data("d", &[4, 5, 6]), Ok(()));
+        assert_eq!(map.get_data("d"), Ok(&[1, 2, 3][..]));
+        {
+            let iter = map.get_data_iter("d");
+            assert!(iter.is_ok());
+            let mut iter = iter.unwrap();
+            assert_eq!(iter.next(), Some(&[1, 2, 3][..]));
+            assert_eq!(iter.next(), Some(&[4, 5, 6][..]));
+            assert_eq!(iter.next(), None);
+        }
+
+        assert_eq!(map.set("d", &&[1, 2, 3][..]), Ok(()));
+        assert_eq!(map.get("d"), Ok(&[1, 2, 3][..]));
+        assert_eq!(map.append("d", &&[4, 5, 6][..]), Ok(()));
+        assert_eq!(map.get("d"), Ok(&[1, 2, 3][..]));
+        {
+            let iter = map.get_iter::<&[u8]>("d");
+            assert!(iter.is_ok());
+            let mut iter = iter.unwrap();
+            assert_eq!(iter.next(), Some(&[1, 2, 3][..]));
+            assert_eq!(iter.next(), Some(&[4, 5, 6][..]));
+            assert_eq!(iter.next(), None);
+        }
+
+        // TODO: node, frame and function method tests when we can make them.
+
+        assert_eq!(map.delete_key("test_frame"), Ok(()));
+        assert_eq!(map.delete_key("test_frame"), Err(map::Error::KeyNotFound));
+
+        assert_eq!(map.error(), None);
+        assert_eq!(map.set_error("hello there"), Ok(()));
+        assert_eq!(
+            map.error().as_ref().map(|x| x.as_ref()),
+            Some("hello there")
+        );
+    }
+
+    #[test]
+    fn map_values() {
+        let mut map = OwnedMap::new(API::get().unwrap());
+        assert_eq!(map.set_int("i", 1), Ok(()));
+        assert_eq!(map.append_int("i", 2), Ok(()));
+        assert_eq!(map.set_float("f", 0.5), Ok(()));
+        assert_eq!(map.set_data("d", &[1, 2, 3]), Ok(()));
+        assert_eq!(map.touch("empty", ValueType::Frame), Ok(()));
+
+        match map.get_values("i") {
+            Ok(map::ValueRef::Ints(iter)) => assert_eq!(iter.collect::<Vec<_>>(), [1, 2]),
+            x => panic!("unexpected values: {:?}", x),
+        }
+        assert_eq!(
+            map.get_values("missing").err(),
+            Some(map::Error::KeyNotFound)
+        );
+
+        // Keys are sorted by VapourSynth.
+        let values = map
+            .iter()
+            .map(|(key, values)| (key, values.value_type(), values.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                ("d", ValueType::Data, 1),
+                ("empty", ValueType::Frame, 0),
+                ("f", ValueType::Float, 1),
+                ("i", ValueType::Int, 2),
+            ]
+        );
+
+        for (key, values) in &*map {
+            if let map::ValueRef::Data(mut iter) = values {
+                assert_eq!(key, "d");
+                assert_eq!(iter.next(), Some(&[1, 2, 3][..]));
+            }
+        }
+    }
+
+    #[test]
+    fn map_clone_and_merge() {
+        let mut map = OwnedMap::new(API::get().unwrap());
+        assert_eq!(map.set_int("i", 1), Ok(()));
+        assert_eq!(map.append_int("i", 2), Ok(()));
+        assert_eq!(map.set_data("d", b"spline36"), Ok(()));
+        assert_eq!(map.touch("empty", ValueType::Float), Ok(()));
+
+        let copy = map.clone();
+        assert_eq!(copy, map);
+        assert_eq!(copy.to_string(), "d: \"spline36\"\nempty: \ni: 1, 2\n");
+        assert_eq!(
+            format!("{:?}", copy),
+            "{\"d\": [[115, 112, 108, 105, 110, 101, 51, 54]], \"empty\": [], \"i\": [1, 2]}"
+        );
+
+        assert_eq!(map.append_int("i", 3), Ok(()));
+        assert_ne!(copy, map);
+
+        let mut other = OwnedMap::new(API::get().unwrap());
+        assert_eq!(other.set_int("i", 10), Ok(()));
+        assert_eq!(other.set_float("f", 0.5), Ok(()));
+
+        let mut merged = copy.clone();
+        assert_eq!(merged.merge(&other, false), Ok(()));
+        assert_eq!(
+            merged.get_int_iter("i").unwrap().collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(merged.get_float("f"), Ok(0.5));
+        assert_eq!(merged.key_count(), 4);
+
+        assert_eq!(merged.merge(&other, true), Ok(()));
+        assert_eq!(merged.get_int_iter("i").unwrap().collect::<Vec<_>>(), [10]);
+
+        // Nodes, frames and functions are copied by reference.
+        let api = API::get().unwrap();
+        let core = api.create_core(1);
+        let std = core.get_plugin_by_namespace("std").unwrap().unwrap();
+        let clip = std
+            .invoke("BlankClip", &OwnedMap::new(api))
+            .unwrap()
+            .get_node("clip")
+            .unwrap();
+        let frame = clip.get_frame(0).unwrap();
+        let function = Function::new(api, core, |_, _, _, _| {});
+
+        let mut map = OwnedMap::new(api);
+        assert_eq!(map.set_node("clip", &clip), Ok(()));
+        assert_eq!(map.set_frame("frame", &frame), Ok(()));
+        assert_eq!(map.set_function("function", &function), Ok(()));
+
+        let copy = map.clone();
+        assert_eq!(
+            copy.get_frame("frame").map(|x| x.data_ptr(0)),
+            Ok(frame.data_ptr(0))
+        );
+        assert!(copy.get_function("function").is_ok());
+        assert_eq!(copy.get_values("clip"), map.get_values("clip"));
+        assert_eq!(copy.get_values("frame"), map.get_values("frame"));
+
+        // Functions have no identity, so maps holding them are never equal.
+        assert_ne!(copy.get_values("function"), map.get_values("function"));
+        assert_ne!(map.get_values("function"), map.get_values("function"));
+        assert_ne!(copy, map);
+    }
+
+    #[test]
+    fn map_macro() {
+        let map = map! {
+            "width" => 1920,
+            "scale" => 0.5,
+            "kernel" => "spline36",
+            "planes" => vec![0, 1, 2],
+            "empty" => Vec::<f64>::new(),
+        }
+        .unwrap();
+        assert_eq!(map.get_int("width"), Ok(1920));
+        assert_eq!(map.get_float("scale"), Ok(0.5));
+        assert_eq!(map.get_data("kernel"), Ok(&b"spline36"[..]));
+        assert_eq!(
+            map.get_int_iter("planes").unwrap().collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(map.value_type("empty"), Ok(ValueType::Float));
+        assert_eq!(map.value_count("empty"), Ok(0));
+
+        // Empty arrays don't bypass the type check.
+        let mut map = map;
+        assert_eq!(
+            map.append_value("empty", Vec::<i64>::new()),
+            Err(map::Error::Key {
+                key: "empty".to_owned(),
+                error: Box::new(map::Error::WrongValueType),
+            })
+        );
+        assert_eq!(
+            map.append_value("width", &[][..] as &[f64]).err(),
+            Some(map::Error::Key {
+                key: "width".to_owned(),
+                error: Box::new(map::Error::WrongValueType),
+            })
+        );
+        assert_eq!(
+            map.touch("scale", ValueType::Int),
+            Err(map::Error::WrongValueType)
+        );
+        assert_eq!(map.touch("scale", ValueType::Float), Ok(()));
+        assert_eq!(map.value_type("empty"), Ok(ValueType::Float));
+
+        // `?` in a value returns from the enclosing function.
+        fn early_return() -> Result<OwnedMap<'static>, String> {
+            let value: Result<i64, String> = Err("early".to_owned());
+            map! { "x" => value? }.map_err(|err| err.to_string())
+        }
+        assert_eq!(early_return().err(), Some("early".to_owned()));
+
+        assert_eq!(
+            map! { "width" => 1920, "bad key" => 1 }.unwrap_err(),
+            map::Error::Key {
+                key: "bad key".to_owned(),
+                error: Box::new(map::Error::InvalidKey(
+                    map::InvalidKeyError::InvalidCharacter(3)
+                )),
+            }
+        );
+        assert_eq!(
+            map! { "width" => 1920, "width" => 1.5 }
+                .unwrap_err()
+                .to_string(),
+            "Couldn't store the value for key `width`: The given/requested value type doesn't \
+             match the type of the property"
+        );
+
+        let map = vec![("a", 1), ("b", 2), ("a", 3)]
+            .into_iter()
+            .collect::<OwnedMap>();
+        assert_eq!(map.get_int_iter("a").unwrap().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(map.get_int("b"), Ok(2));
+        assert!(OwnedMap::try_from_iter(vec![("", 1)]).is_err());
+    }
+
+    #[test]
+    fn map_strings() {
+        let api = API::get().unwrap();
+        let mut map = OwnedMap::new(api);
+
+        map.set_str("kernel", "spline36").unwrap();
+        map.append_str("kernel", "bicubic").unwrap();
+        map.set("name", &"ñame".to_owned()).unwrap();
+        map.set_data("invalid", &[b'a', 0xff]).unwrap();
+        map.append_str("invalid", "valid").unwrap();
+
+        assert_eq!(map.get_str("kernel"), Ok("spline36"));
+        assert_eq!(map.get::<String>("name"), Ok("ñame".to_owned()));
+        assert_eq!(
+            map.get_str_iter("kernel").unwrap().collect::<Vec<_>>(),
+            ["spline36", "bicubic"]
+        );
+        assert_eq!(
+            map.get_iter::<String>("kernel")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["spline36", "bicubic"]
+        );
+        assert_eq!(map.get_str("missing"), Err(map::Error::KeyNotFound));
+
+        let error = map.get_str("invalid").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The value of key `invalid` isn't valid UTF-8"
+        );
+        match map.get_str_iter("invalid") {
+            Err(map::Error::InvalidUtf8 { ref key, .. }) if key == "invalid" => {}
+            x => panic!("unexpected result: {:?}", x.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn filter_argument_conversions() {
+        use std::path::PathBuf;
+
+        use plugins::{EnumArgument, FilterParameter};
+
+        #[derive(Debug, PartialEq)]
+        enum Mode {
+            Fast,
+            Slow,
+        }
+
+        impl EnumArgument for Mode {
+            type Repr = str;
+
+            fn from_repr(value: &str) -> Option<Self> {
+                match value {
+                    "fast" => Some(Mode::Fast),
+                    "slow" => Some(Mode::Slow),
+                    _ => None,
+                }
+            }
+        }
+
+        #[derive(Debug, PartialEq)]
+        enum Level {
+            Low = 1,
+            High = 2,
         }
 
-        assert_eq!(map.set_data("d", &[1, 2, 3]), Ok(()));
-        assert_eq!(map.get_data("d"), Ok(&[1, 2, 3][..]));
-        assert_eq!(map.append_data("d", &[4, 5, 6]), Ok(()));
-        assert_eq!(map.get_data("d"), Ok(&[1, 2, 3][..]));
-        {
-            let iter = map.get_data_iter("d");
-            assert!(iter.is_ok());
-            let mut iter = iter.unwrap();
-            assert_eq!(iter.next(), Some(&[1, 2, 3][..]));
-            assert_eq!(iter.next(), Some(&[4, 5, 6][..]));
-            assert_eq!(iter.next(), None);
+        impl EnumArgument for Level {
+            type Repr = i64;
+
+            fn from_repr(&value: &i64) -> Option<Self> {
+                match value {
+                    1 => Some(Level::Low),
+                    2 => Some(Level::High),
+                    _ => None,
+                }
+            }
         }
 
-        assert_eq!(map.set("d", &&[1, 2, 3][..]), Ok(()));
-        assert_eq!(map.get("d"), Ok(&[1, 2, 3][..]));
-        assert_eq!(map.append("d", &&[4, 5, 6][..]), Ok(()));
-        assert_eq!(map.get("d"), Ok(&[1, 2, 3][..]));
-        {
-            let iter = map.get_iter::<&[u8]>("d");
-            assert!(iter.is_ok());
-            let mut iter = iter.unwrap();
-            assert_eq!(iter.next(), Some(&[1, 2, 3][..]));
-            assert_eq!(iter.next(), Some(&[4, 5, 6][..]));
-            assert_eq!(iter.next(), None);
+        fn get<'map, T: FilterParameter<'map, 'map>>(
+            map: &'map Map<'map>,
+            key: &str,
+        ) -> map::Result<T> {
+            T::get_from_map(map, key)
         }
 
-        // TODO: node, frame and function method tests when we can make them.
+        let mut map = OwnedMap::new(API::get().unwrap());
+        map.set_int("small", 200).unwrap();
+        map.set_int("negative", -1).unwrap();
+        map.set_int("huge", 1 << 40).unwrap();
+        map.set_float("float", 0.5).unwrap();
+        map.set_float("huge_float", 1e300).unwrap();
+        map.set_str("path", "/tmp/file.txt").unwrap();
+        map.set_str("mode", "slow").unwrap();
+        map.set_str("bad_mode", "medium").unwrap();
+        map.set_int("level", 2).unwrap();
+        map.set_int("bad_level", 3).unwrap();
+
+        assert_eq!(get::<u8>(&map, "small"), Ok(200));
+        assert_eq!(get::<i32>(&map, "negative"), Ok(-1));
+        assert_eq!(
+            get::<u32>(&map, "huge"),
+            Err(map::Error::ArgumentOutOfRange {
+                key: "huge".to_owned(),
+                message: format!("{} is not in 0..=4294967295", 1i64 << 40),
+            })
+        );
+        assert_eq!(
+            get::<usize>(&map, "negative").unwrap_err().to_string(),
+            format!(
+                "Argument `negative` out of range: -1 is not in 0..={}",
+                usize::MAX
+            )
+        );
+        assert_eq!(get::<bool>(&map, "small"), Ok(true));
+        assert_eq!(get::<Option<bool>>(&map, "missing"), Ok(None));
+        assert!(get::<Option<u8>>(&map, "negative").is_err());
 
-        assert_eq!(map.delete_key("test_frame"), Ok(()));
-        assert_eq!(map.delete_key("test_frame"), Err(map::Error::KeyNotFound));
+        assert_eq!(get::<f32>(&map, "float"), Ok(0.5));
+        assert!(get::<f32>(&map, "huge_float").is_err());
 
-        assert_eq!(map.error(), None);
-        assert_eq!(map.set_error("hello there"), Ok(()));
         assert_eq!(
-            map.error().as_ref().map(|x| x.as_ref()),
-            Some("hello there")
+            get::<PathBuf>(&map, "path"),
+            Ok(PathBuf::from("/tmp/file.txt"))
         );
+
+        assert_eq!(get::<Mode>(&map, "mode"), Ok(Mode::Slow));
+        assert_eq!(
+            get::<Mode>(&map, "bad_mode").unwrap_err().to_string(),
+            "Argument `bad_mode` out of range: \"medium\" isn't a valid value"
+        );
+        assert_eq!(get::<Option<Level>>(&map, "level"), Ok(Some(Level::High)));
+        assert!(get::<Level>(&map, "bad_level").is_err());
+        assert_eq!(get::<Level>(&map, "mode"), Err(map::Error::WrongValueType));
+    }
+
+    #[test]
+    fn api_version_negotiation() {
+        use api::ApiVersion;
+        use vapoursynth_sys as ffi;
+
+        let api = API::get().unwrap();
+        let version = api.version();
+        assert!(version >= ApiVersion::from_raw(ffi::VAPOURSYNTH_API_VERSION));
+
+        let core = api.create_core(1);
+        assert!(version.into_raw() <= core.info().api_version);
+
+        for &capability in &[
+            Capability::PropArrays,
+            Capability::PluginPath,
+            Capability::LogMessage,
+            Capability::AddMessageHandler,
+            Capability::CoreSettings,
+            Capability::CoreInfo2,
+        ] {
+            assert_eq!(
+                api.supports(capability),
+                version >= capability.required_version()
+            );
+        }
+
+        let plugin = core.get_plugin_by_namespace("std").unwrap().unwrap();
+        match plugin.path() {
+            Ok(path) => {
+                assert!(api.supports(Capability::PluginPath));
+                assert!(path.is_some());
+            }
+            Err(error) => {
+                assert!(!api.supports(Capability::PluginPath));
+                assert_eq!(error.capability(), Capability::PluginPath);
+                assert_eq!(error.version(), version);
+            }
+        }
     }
 
     // This test is commented out because it currently deadlocks due to
@@ -923,9 +1925,11 @@ mod need_api {
         let (tx, rx) = channel();
 
         // Hopefully no one logs anything here and breaks the test.
-        let id = api.add_message_handler(move |message_type, message| {
-            assert_eq!(tx.send((message_type, message.to_owned())), Ok(()));
-        });
+        let id = api
+            .add_message_handler(move |message_type, message| {
+                assert_eq!(tx.send((message_type, message.to_owned())), Ok(()));
+            })
+            .unwrap();
 
         assert_eq!(
             api.log(MessageType::Warning, "test warning message"),
@@ -974,7 +1978,8 @@ mod need_api {
                 let guard = SENDER.lock().unwrap();
                 let tx = guard.as_ref().unwrap();
                 assert_eq!(tx.send((message_type, message.to_owned())), Ok(()));
-            });
+            })
+            .unwrap();
 
             assert_eq!(
                 api.log(MessageType::Warning, "test warning message"),
@@ -1014,6 +2019,136 @@ mod need_api {
         api.clear_message_handler();
     }
 
+    #[test]
+    fn format_builder() {
+        let api = API::get().unwrap();
+        let core = api.create_core(1);
+
+        let yuv420p10 = FormatBuilder::new(ColorFamily::YUV)
+            .bits_per_sample(10)
+            .sub_sampling(1, 1)
+            .build(core);
+        assert_eq!(yuv420p10, core.get_format(PresetFormat::YUV420P10.into()));
+        let yuv420p10 = yuv420p10.unwrap();
+        assert_eq!(yuv420p10.to_string(), "YUV420P10");
+        assert_eq!(yuv420p10.to_builder().build(core), Some(yuv420p10));
+
+        let resolution = Resolution {
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(yuv420p10.plane_resolution(0, resolution), resolution);
+        assert_eq!(
+            yuv420p10.plane_resolution(2, resolution),
+            Resolution {
+                width: 960,
+                height: 540,
+            }
+        );
+
+        let rgbs = FormatBuilder::new(ColorFamily::RGB)
+            .sample_type(SampleType::Float)
+            .bits_per_sample(32)
+            .build(core);
+        assert_eq!(rgbs, core.get_format(PresetFormat::RGBS.into()));
+
+        let subsampled_rgb = FormatBuilder::new(ColorFamily::RGB)
+            .sub_sampling(1, 1)
+            .build(core);
+        assert!(subsampled_rgb.is_none());
+    }
+
+    #[test]
+    fn format_constraint() {
+        let api = API::get().unwrap();
+        let core = api.create_core(1);
+
+        let info = |format, width| VideoInfo {
+            format,
+            framerate: Property::Variable,
+            resolution: Property::Constant(Resolution { width, height: 240 }),
+            #[cfg(feature = "gte-vapoursynth-api-32")]
+            num_frames: 1,
+            #[cfg(not(feature = "gte-vapoursynth-api-32"))]
+            num_frames: Property::Constant(1),
+            flags: Flags::empty(),
+        };
+        let format = |preset: PresetFormat| core.get_format(preset.into()).unwrap();
+
+        let constraint = FormatConstraint::new()
+            .constant_format()
+            .color_families(&[ColorFamily::YUV, ColorFamily::Gray])
+            .sample_types(&[SampleType::Integer])
+            .bits_per_sample(8..=16)
+            .width_modulo(2);
+
+        assert!(constraint
+            .check(
+                "clip",
+                &info(Property::Constant(format(PresetFormat::YUV420P8)), 320)
+            )
+            .is_ok());
+        assert!(constraint
+            .check(
+                "clip",
+                &info(Property::Constant(format(PresetFormat::Gray16)), 320)
+            )
+            .is_ok());
+
+        let violation = |format, width| {
+            constraint
+                .check("clip", &info(format, width))
+                .unwrap_err()
+                .violation()
+        };
+        assert_eq!(
+            violation(Property::Variable, 320),
+            FormatConstraintViolation::VariableFormat
+        );
+        assert_eq!(
+            violation(Property::Constant(format(PresetFormat::RGB24)), 320),
+            FormatConstraintViolation::ColorFamily(ColorFamily::RGB)
+        );
+        assert_eq!(
+            violation(Property::Constant(format(PresetFormat::YUV444PS)), 320),
+            FormatConstraintViolation::SampleType(SampleType::Float)
+        );
+        assert_eq!(
+            FormatConstraint::new()
+                .bits_per_sample(8..=8)
+                .check(
+                    "clip",
+                    &info(Property::Constant(format(PresetFormat::Gray16)), 320)
+                )
+                .unwrap_err()
+                .violation(),
+            FormatConstraintViolation::BitsPerSample(16)
+        );
+        assert_eq!(
+            violation(Property::Constant(format(PresetFormat::YUV420P8)), 321),
+            FormatConstraintViolation::Width(321)
+        );
+
+        let err = constraint
+            .check(
+                "clips",
+                &info(Property::Constant(format(PresetFormat::RGB24)), 320),
+            )
+            .unwrap_err();
+        assert_eq!(err.argument(), "clips");
+        assert_eq!(
+            err.to_string(),
+            "Argument `clips`: color family RGB is not supported \
+             (expected constant format, YUV/Gray, Integer, 8–16-bit, mod-2 width)"
+        );
+
+        // Variable formats are accepted unless a constant format is required.
+        assert!(FormatConstraint::new()
+            .sample_types(&[SampleType::Integer])
+            .check("clip", &info(Property::Variable, 321))
+            .is_ok());
+    }
+
     #[test]
     fn create_core() {
         let api = API::get().unwrap();
@@ -1040,10 +2175,535 @@ mod need_api {
 
         #[cfg(feature = "gte-vapoursynth-api-36")]
         {
-            assert_eq!(core.set_max_cache_size(1337), 1337);
-            assert_eq!(core.set_thread_count(3), 3);
+            assert_eq!(core.set_max_cache_size(1337), Ok(1337));
+            assert_eq!(core.set_thread_count(3), Ok(3));
             assert_eq!(core.info().max_framebuffer_size, 1337);
             assert_eq!(core.info().num_threads, 3);
         }
     }
 }
+
+mod no_api {
+    use std::ffi::CString;
+    use std::time::Duration;
+
+    use super::*;
+    use core::CreateFilterError;
+    use format::IntoEnumIterator;
+    use prelude::*;
+    use video_info::{Framerate, Resolution, VideoInfoBuilder, VideoInfoError};
+
+    #[test]
+    fn preset_format_names() {
+        assert_eq!(PresetFormat::YUV420P10.to_string(), "YUV420P10");
+        assert_eq!("YUV420P10".parse(), Ok(PresetFormat::YUV420P10));
+        assert_eq!("GRAY8".parse(), Ok(PresetFormat::Gray8));
+        assert_eq!("compatbgr32".parse(), Ok(PresetFormat::CompatBGR32));
+        assert!("YUV420P11".parse::<PresetFormat>().is_err());
+
+        for preset in PresetFormat::iter() {
+            assert_eq!(preset.to_string().parse(), Ok(preset));
+        }
+        assert_eq!(PresetFormat::iter().count(), 35);
+    }
+
+    #[test]
+    fn format_constraint_arguments() {
+        use plugins::ffi::invalid_constraint_argument;
+        use plugins::FormatConstraint;
+
+        let args = "clip:clip;clips:clip[]:opt;planes:int[]:opt;";
+        let constraint = FormatConstraint::new;
+
+        assert_eq!(invalid_constraint_argument(args, &[]), None);
+        assert_eq!(
+            invalid_constraint_argument(args, &[("clip", constraint()), ("clips", constraint())]),
+            None
+        );
+        assert_eq!(
+            invalid_constraint_argument(args, &[("clip", constraint()), ("clp", constraint())]),
+            Some("clp")
+        );
+        assert_eq!(
+            invalid_constraint_argument(args, &[("planes", constraint())]),
+            Some("planes")
+        );
+    }
+
+    #[test]
+    fn video_info_builder() {
+        let ntsc = Framerate {
+            numerator: 60000,
+            denominator: 2002,
+        };
+        assert!(!ntsc.is_reduced());
+        let ntsc = ntsc.reduced();
+        assert_eq!(
+            ntsc,
+            Framerate {
+                numerator: 30000,
+                denominator: 1001,
+            }
+        );
+        assert!(ntsc.is_reduced());
+
+        let info = VideoInfoBuilder::new()
+            .resolution(Resolution {
+                width: 640,
+                height: 480,
+            })
+            .framerate(ntsc)
+            .num_frames(30000)
+            .build()
+            .unwrap();
+        assert_eq!(info.duration(), Some(Duration::from_secs(1001)));
+        assert_eq!(info.frame_time(0), Some(Duration::from_secs(0)));
+        assert_eq!(info.frame_time(3), Some(Duration::from_nanos(100_100_000)));
+        assert_eq!(
+            info.frame_at_time(Duration::from_nanos(100_100_000)),
+            Some(3)
+        );
+        assert_eq!(
+            info.frame_at_time(Duration::from_nanos(100_099_999)),
+            Some(2)
+        );
+        assert_eq!(info.frame_at_time(Duration::from_secs(1001)), None);
+
+        let variable = info
+            .to_builder()
+            .framerate(Property::Variable)
+            .build()
+            .unwrap();
+        assert_eq!(variable.duration(), None);
+        assert_eq!(variable.frame_at_time(Duration::from_secs(1)), None);
+
+        assert_eq!(
+            info.to_builder()
+                .resolution(Resolution {
+                    width: 0,
+                    height: 480,
+                })
+                .build()
+                .unwrap_err(),
+            VideoInfoError::ZeroResolution
+        );
+        assert_eq!(
+            info.to_builder()
+                .framerate(Framerate {
+                    numerator: 60,
+                    denominator: 0,
+                })
+                .build()
+                .unwrap_err(),
+            VideoInfoError::ZeroFramerate
+        );
+        assert_eq!(
+            info.to_builder()
+                .framerate(Framerate {
+                    numerator: 60,
+                    denominator: 2,
+                })
+                .build()
+                .unwrap_err(),
+            VideoInfoError::FramerateNotReduced(Framerate {
+                numerator: 60,
+                denominator: 2,
+            })
+        );
+        assert_eq!(
+            info.to_builder().num_frames(0).build().unwrap_err(),
+            VideoInfoError::ZeroLength
+        );
+    }
+
+    #[test]
+    fn framerate_round_trip() {
+        let framerates = [
+            (24000, 1001),
+            (30000, 1001),
+            (60000, 1001),
+            (25, 1),
+            (60, 1),
+            (1, 3),
+        ];
+        for &(numerator, denominator) in &framerates {
+            let framerate = Framerate {
+                numerator,
+                denominator,
+            };
+
+            for n in (0..10_000).chain((14..48).map(|x| 1 << x)) {
+                assert_eq!(framerate.frame_at_time(framerate.frame_time(n)), n);
+                if n > 0 {
+                    let before = framerate.frame_time(n) - Duration::from_nanos(1);
+                    assert_eq!(framerate.frame_at_time(before), n - 1);
+                }
+            }
+        }
+
+        let ntsc = Framerate {
+            numerator: 30000,
+            denominator: 1001,
+        };
+        assert_eq!(ntsc.frame_time(1), Duration::from_nanos(33_366_667));
+
+        let fast = Framerate {
+            numerator: u64::MAX,
+            denominator: 1,
+        };
+        assert_eq!(fast.frame_at_time(Duration::MAX), usize::MAX);
+    }
+
+    #[test]
+    fn filter_error_messages() {
+        let error: plugins::Error = anyhow::Error::msg("oh no")
+            .context("while inverting")
+            .into();
+        let mut buf = error.to_string();
+        plugins::ffi::push_backtrace(&mut buf, &*error);
+        assert!(buf.starts_with("while inverting\nCaused by: oh no"));
+
+        let error: plugins::Error =
+            CreateFilterError::InvalidName(CString::new("a\0").unwrap_err()).into();
+        let mut buf = error.to_string();
+        plugins::ffi::push_backtrace(&mut buf, &*error);
+        assert_eq!(
+            buf,
+            "Couldn't convert the filter name to a CString\n\
+             Caused by: nul byte found in provided data at position: 1"
+        );
+    }
+
+    #[test]
+    fn edge_modes() {
+        use node::EdgeMode;
+
+        let clamp = |n| EdgeMode::Clamp.frame_number(n, 5);
+        assert_eq!(
+            (-3..8).map(clamp).collect::<Vec<_>>(),
+            [0, 0, 0, 0, 1, 2, 3, 4, 4, 4, 4]
+        );
+
+        let mirror = |n| EdgeMode::Mirror.frame_number(n, 5);
+        assert_eq!(
+            (-3..8).map(mirror).collect::<Vec<_>>(),
+            [3, 2, 1, 0, 1, 2, 3, 4, 3, 2, 1]
+        );
+        assert_eq!(mirror(-9), 1);
+        assert_eq!(mirror(13), 3);
+
+        assert_eq!(EdgeMode::Mirror.frame_number(-5, 1), 0);
+        assert_eq!(EdgeMode::Mirror.frame_number(3, 2), 1);
+        assert_eq!(
+            EdgeMode::Clamp.frame_number(i64::MAX, usize::MAX),
+            i64::MAX as usize
+        );
+    }
+
+    #[cfg(feature = "runtime-loading")]
+    #[test]
+    fn runtime_loading_errors() {
+        use std::path::Path;
+
+        let path = Path::new("/nonexistent/libvapoursynth");
+
+        #[cfg(feature = "vapoursynth-functions")]
+        {
+            let error = api::load_library(Some(path)).unwrap_err();
+            assert_eq!(error.path(), path);
+            assert!(error
+                .to_string()
+                .starts_with("Couldn't load /nonexistent/libvapoursynth: "));
+        }
+
+        #[cfg(feature = "vsscript-functions")]
+        {
+            let error = vsscript::load_library(Some(path)).unwrap_err();
+            assert_eq!(error.path(), path);
+            assert!(!error.message().is_empty());
+        }
+    }
+
+    #[test]
+    fn plugin_state() {
+        use plugins::plugin_state;
+        use plugins::state::{with_state, AnyState};
+        use std::sync::Arc;
+
+        assert!(plugin_state::<i32>().is_none());
+
+        let state: AnyState = Arc::new(42i32);
+        with_state(Some(&state), || {
+            assert_eq!(plugin_state::<i32>().as_deref(), Some(&42));
+            assert!(plugin_state::<u8>().is_none());
+
+            with_state(None, || assert!(plugin_state::<i32>().is_none()));
+            assert_eq!(plugin_state::<i32>().as_deref(), Some(&42));
+        });
+
+        assert!(plugin_state::<i32>().is_none());
+    }
+
+    #[test]
+    fn extended_filter_argument_types() {
+        use std::path::PathBuf;
+
+        use api::API;
+        use core::CoreRef;
+        use map::Map;
+        use plugins::{EnumArgument, Error, Filter, FilterFunction};
+
+        enum Mode {
+            Fast,
+        }
+
+        impl EnumArgument for Mode {
+            type Repr = str;
+
+            fn from_repr(value: &str) -> Option<Self> {
+                match value {
+                    "fast" => Some(Mode::Fast),
+                    _ => None,
+                }
+            }
+        }
+
+        make_filter_function! {
+            ExtendedFunction, "Extended"
+
+            fn create_extended<'core>(
+                _api: API,
+                _core: CoreRef<'core>,
+                enabled: bool,
+                radius: u8,
+                planes: Option<u32>,
+                path: PathBuf,
+                mode: Option<Mode>,
+            ) -> Result<Option<Box<dyn Filter<'core> + 'core>>, Error> {
+                let _ = (enabled, radius, planes, path, mode);
+                Ok(None)
+            }
+        }
+
+        assert_eq!(
+            ExtendedFunction::new().args(),
+            "enabled:int;radius:int;planes:int:opt;path:data;mode:data:opt;"
+        );
+    }
+    #[test]
+    fn api_capabilities() {
+        use api::{ApiVersion, UnsupportedError};
+
+        let version = ApiVersion::from_raw((3 << 16) | 4);
+        assert_eq!(version, ApiVersion { major: 3, minor: 4 });
+        assert_eq!(version.into_raw(), 0x0003_0004);
+        assert_eq!(version.to_string(), "3.4");
+        assert!(version < ApiVersion { major: 3, minor: 6 });
+        assert!(version > ApiVersion { major: 3, minor: 1 });
+
+        assert_eq!(
+            Capability::PluginPath.required_version(),
+            ApiVersion { major: 3, minor: 1 }
+        );
+        assert_eq!(Capability::LogMessage.required_version(), version);
+        assert_eq!(
+            Capability::AddMessageHandler.required_version(),
+            ApiVersion { major: 3, minor: 6 }
+        );
+
+        let error = UnsupportedError::new(Capability::AddMessageHandler, version);
+        assert_eq!(
+            error.to_string(),
+            "AddMessageHandler requires VapourSynth API 3.6, but only API 3.4 is available"
+        );
+        assert_eq!(
+            map::Error::from(error).to_string(),
+            "The operation isn't supported by the core"
+        );
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_levels() {
+        use logging::{log_level, message_type};
+
+        assert_eq!(log_level(MessageType::Debug), log::Level::Debug);
+        assert_eq!(log_level(MessageType::Warning), log::Level::Warn);
+        assert_eq!(log_level(MessageType::Critical), log::Level::Error);
+        assert_eq!(log_level(MessageType::Fatal), log::Level::Error);
+
+        assert_eq!(message_type(log::Level::Error), MessageType::Critical);
+        assert_eq!(message_type(log::Level::Warn), MessageType::Warning);
+        assert_eq!(message_type(log::Level::Info), MessageType::Debug);
+        assert_eq!(message_type(log::Level::Trace), MessageType::Debug);
+
+        // Records never abort the process and messages keep their severity.
+        for &level in &[log::Level::Error, log::Level::Warn, log::Level::Debug] {
+            assert_ne!(message_type(level), MessageType::Fatal);
+            assert_eq!(log_level(message_type(level)), level);
+        }
+    }
+
+    #[test]
+    fn profiling() {
+        use plugins::profiling::{self, CallTimer, FilterStats, Report};
+
+        profiling::set_enabled(true);
+        let entry = profiling::register("ProfilingTest");
+
+        {
+            let _timer = CallTimer::start(|| &entry, true);
+        }
+        {
+            let _timer = CallTimer::start(|| &entry, false);
+            profiling::record_frame_allocation();
+            profiling::record_frame_allocation();
+        }
+        // Allocations outside of the frame functions aren't counted.
+        profiling::record_frame_allocation();
+
+        let report = profiling::report();
+        let stats = report
+            .filters()
+            .iter()
+            .find(|stats| stats.name == "ProfilingTest")
+            .unwrap();
+        assert_eq!(stats.instances, 1);
+        assert_eq!(stats.initial_calls, 1);
+        assert_eq!(stats.calls, 1);
+        assert_eq!(stats.frames_allocated, 2);
+
+        let report = Report::new(vec![
+            FilterStats {
+                name: "Fast".to_owned(),
+                instances: 1,
+                calls: 10,
+                time: Duration::from_millis(2),
+                ..FilterStats::default()
+            },
+            FilterStats {
+                name: "Slow \"one\"".to_owned(),
+                instances: 2,
+                initial_calls: 5,
+                initial_time: Duration::from_micros(1500),
+                calls: 5,
+                time: Duration::from_millis(40),
+                frames_allocated: 5,
+            },
+        ]);
+        assert_eq!(report.filters()[0].name, "Slow \"one\"");
+        assert_eq!(
+            report.to_string(),
+            "Filter     Instances   Initial Initial (ms)    Frames  Frames (ms) Allocated\n\
+             Slow \"one\"         2         5        1.500         5       40.000         5\n\
+             Fast               1         0        0.000        10        2.000         0\n"
+        );
+        assert_eq!(
+            report.to_json(),
+            "{\"filters\":[\
+             {\"name\":\"Slow \\\"one\\\"\",\"instances\":2,\"initial_calls\":5,\
+             \"initial_time_ns\":1500000,\"calls\":5,\"time_ns\":40000000,\"frames_allocated\":5},\
+             {\"name\":\"Fast\",\"instances\":1,\"initial_calls\":0,\"initial_time_ns\":0,\
+             \"calls\":10,\"time_ns\":2000000,\"frames_allocated\":0}]}"
+        );
+    }
+
+    #[test]
+    fn graph_export() {
+        use graph::{self, Argument, Graph};
+
+        // Made-up node IDs and instance addresses.
+        graph::record_ids(
+            0xa,
+            "Split",
+            vec![
+                ("clip".to_owned(), Argument::Nodes(vec![0x1000])),
+                ("planes".to_owned(), Argument::Ints(vec![0, 1])),
+                (
+                    "mode".to_owned(),
+                    Argument::Data(vec![b"fast \"x\"".to_vec()]),
+                ),
+            ],
+            &[0x2000, 0x2008],
+        );
+        graph::record_ids(
+            0xb,
+            "Merge",
+            vec![
+                ("clipa".to_owned(), Argument::Nodes(vec![0x2000])),
+                ("clipb".to_owned(), Argument::Nodes(vec![0x2008])),
+                ("weight".to_owned(), Argument::Floats(vec![0.5])),
+                ("callback".to_owned(), Argument::Functions(1)),
+            ],
+            &[0x3000],
+        );
+
+        let graph = Graph::from_id(0x3000);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph {\n    \
+             n0 [label=\"Merge\\nweight=0.5\\ncallback=<function>\"];\n    \
+             n1 [label=\"Split[0]\\nplanes=0, 1\\nmode=fast \\\"x\\\"\"];\n    \
+             n2 [label=\"Split[1]\\nplanes=0, 1\\nmode=fast \\\"x\\\"\"];\n    \
+             n3 [label=\"?\"];\n    \
+             n1 -> n0 [label=\"clipa\"];\n    \
+             n2 -> n0 [label=\"clipb\"];\n    \
+             n3 -> n1 [label=\"clip\"];\n    \
+             n3 -> n2 [label=\"clip\"];\n\
+             }\n"
+        );
+        assert_eq!(
+            graph.to_json(),
+            "{\"nodes\":[\
+             {\"id\":0,\"filter\":\"Merge\",\"output\":0,\"args\":[\
+             {\"name\":\"clipa\",\"type\":\"node\",\"values\":[1]},\
+             {\"name\":\"clipb\",\"type\":\"node\",\"values\":[2]},\
+             {\"name\":\"weight\",\"type\":\"float\",\"values\":[0.5]},\
+             {\"name\":\"callback\",\"type\":\"function\",\"values\":[null]}]},\
+             {\"id\":1,\"filter\":\"Split\",\"output\":0,\"args\":[\
+             {\"name\":\"clip\",\"type\":\"node\",\"values\":[3]},\
+             {\"name\":\"planes\",\"type\":\"int\",\"values\":[0,1]},\
+             {\"name\":\"mode\",\"type\":\"data\",\"values\":[\"fast \\\"x\\\"\"]}]},\
+             {\"id\":2,\"filter\":\"Split\",\"output\":1,\"args\":[\
+             {\"name\":\"clip\",\"type\":\"node\",\"values\":[3]},\
+             {\"name\":\"planes\",\"type\":\"int\",\"values\":[0,1]},\
+             {\"name\":\"mode\",\"type\":\"data\",\"values\":[\"fast \\\"x\\\"\"]}]},\
+             {\"id\":3,\"filter\":null,\"output\":0,\"args\":[]}]}"
+        );
+
+        // Freed filters become unknown sources.
+        graph::forget(0xa);
+        let graph = Graph::from_id(0x3000);
+        assert_eq!(graph.nodes().len(), 3);
+        assert!(graph.nodes()[1].filter.is_none());
+        assert!(graph.nodes()[2].filter.is_none());
+
+        graph::forget(0xb);
+        assert!(Graph::from_id(0x3000).nodes()[0].filter.is_none());
+    }
+
+    #[test]
+    fn cache_access_pattern() {
+        use node::cache::State;
+
+        let mut state = State::default();
+        assert_eq!(state.record_access(10), None);
+        assert_eq!(state.record_access(11), None);
+        assert_eq!(state.record_access(12), Some(1));
+        assert_eq!(state.record_access(13), Some(1));
+
+        // A jump starts a new pattern.
+        assert_eq!(state.record_access(40), None);
+        assert_eq!(state.record_access(38), None);
+        assert_eq!(state.record_access(36), Some(-2));
+        assert_eq!(state.record_access(36), None);
+        assert_eq!(state.record_access(36), None);
+
+        // Frames outside of the clip and frames in flight aren't requested.
+        assert_eq!(state.prefetch_list(36, -2, 3, 100), [34, 32, 30]);
+        assert_eq!(state.prefetch_list(34, -2, 3, 100), [28]);
+        assert_eq!(state.prefetch_list(3, -2, 3, 100), [1]);
+        assert_eq!(state.prefetch_list(97, 1, 4, 100), [98, 99]);
+        assert!(state.prefetch_list(50, 1, 0, 100).is_empty());
+    }
+}
@@ -3,6 +3,7 @@
 use std::fmt::Debug;
 use std::ops::Deref;
 use std::ptr;
+use std::time::Duration;
 use vapoursynth_sys as ffi;
 
 use format::Format;
@@ -28,6 +29,77 @@ pub struct Framerate {
     pub denominator: u64,
 }
 
+impl Framerate {
+    /// Returns this framerate with the numerator and the denominator divided by their greatest
+    /// common divisor.
+    ///
+    /// For example, 60000/2002 becomes 30000/1001.
+    #[inline]
+    pub fn reduced(self) -> Self {
+        let gcd = gcd(self.numerator, self.denominator);
+        if gcd == 0 {
+            return self;
+        }
+
+        Self {
+            numerator: self.numerator / gcd,
+            denominator: self.denominator / gcd,
+        }
+    }
+
+    /// Returns whether the numerator and the denominator have no common divisors.
+    #[inline]
+    pub fn is_reduced(self) -> bool {
+        gcd(self.numerator, self.denominator) == 1
+    }
+
+    /// Returns the timestamp of frame `n` at this framerate.
+    ///
+    /// The timestamp is rounded up to whole nanoseconds, so that `frame_at_time()` maps it back to
+    /// `n` (for framerates below one frame per nanosecond).
+    ///
+    /// # Panics
+    /// Panics if the numerator is zero.
+    #[inline]
+    pub fn frame_time(self, n: usize) -> Duration {
+        assert!(self.numerator > 0);
+
+        let ticks = n as u128 * u128::from(self.denominator);
+        let numerator = u128::from(self.numerator);
+        let secs = ticks / numerator;
+        let nanos = (ticks % numerator * 1_000_000_000).div_ceil(numerator);
+        Duration::new(secs as u64, 0) + Duration::from_nanos(nanos as u64)
+    }
+
+    /// Returns the number of the frame displayed at time `t` at this framerate.
+    ///
+    /// Saturates at `usize::MAX`.
+    ///
+    /// # Panics
+    /// Panics if the denominator is zero.
+    #[inline]
+    pub fn frame_at_time(self, t: Duration) -> usize {
+        assert!(self.denominator > 0);
+
+        let denominator = u128::from(self.denominator) * 1_000_000_000;
+        t.as_nanos()
+            .checked_mul(u128::from(self.numerator))
+            .map_or(usize::MAX, |x| {
+                (x / denominator).min(usize::MAX as u128) as usize
+            })
+    }
+}
+
+/// Returns the greatest common divisor of two numbers.
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
 /// Represents a property that can be either constant or variable, like the resolution or the
 /// framerate.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -63,7 +135,229 @@ pub struct VideoInfo<'core> {
     pub flags: node::Flags,
 }
 
+/// An error indicating that a `VideoInfoBuilder` describes an impossible clip.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum VideoInfoError {
+    /// The width or the height is zero.
+    #[error("The width and the height must be greater than zero")]
+    ZeroResolution,
+    /// The width or the height is not a multiple of the subsampling factor.
+    #[error("The resolution is not compatible with the format subsampling")]
+    IncompatibleSubSampling,
+    /// The framerate numerator or denominator is zero.
+    #[error("The framerate numerator and denominator must be greater than zero")]
+    ZeroFramerate,
+    /// The framerate numerator and denominator have a common divisor.
+    #[error("The framerate {0:?} is not reduced")]
+    FramerateNotReduced(Framerate),
+    /// The number of frames is zero.
+    #[error("The clip must have at least one frame")]
+    ZeroLength,
+}
+
+/// A builder for `VideoInfo`, validating the result.
+///
+/// # Example
+/// ```
+/// # use vapoursynth::video_info::{Framerate, Resolution, VideoInfoBuilder};
+/// let info = VideoInfoBuilder::new()
+///     .resolution(Resolution {
+///         width: 1920,
+///         height: 1080,
+///     })
+///     .framerate(
+///         Framerate {
+///             numerator: 60000,
+///             denominator: 2002,
+///         }
+///         .reduced(),
+///     )
+///     .num_frames(100)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct VideoInfoBuilder<'core> {
+    info: VideoInfo<'core>,
+}
+
+impl<'core> VideoInfoBuilder<'core> {
+    /// Creates a builder for a clip with variable format, framerate and resolution, and no frames.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            info: VideoInfo {
+                format: Property::Variable,
+                framerate: Property::Variable,
+                resolution: Property::Variable,
+                #[cfg(feature = "gte-vapoursynth-api-32")]
+                num_frames: 0,
+                #[cfg(not(feature = "gte-vapoursynth-api-32"))]
+                num_frames: Property::Constant(0),
+                flags: node::Flags::empty(),
+            },
+        }
+    }
+
+    /// Sets the format.
+    #[inline]
+    pub fn format<T: Into<Property<Format<'core>>>>(mut self, format: T) -> Self {
+        self.info.format = format.into();
+        self
+    }
+
+    /// Sets the framerate.
+    #[inline]
+    pub fn framerate<T: Into<Property<Framerate>>>(mut self, framerate: T) -> Self {
+        self.info.framerate = framerate.into();
+        self
+    }
+
+    /// Sets the resolution.
+    #[inline]
+    pub fn resolution<T: Into<Property<Resolution>>>(mut self, resolution: T) -> Self {
+        self.info.resolution = resolution.into();
+        self
+    }
+
+    /// Sets the number of frames.
+    #[inline]
+    pub fn num_frames(mut self, num_frames: usize) -> Self {
+        #[cfg(feature = "gte-vapoursynth-api-32")]
+        {
+            self.info.num_frames = num_frames;
+        }
+        #[cfg(not(feature = "gte-vapoursynth-api-32"))]
+        {
+            self.info.num_frames = Property::Constant(num_frames);
+        }
+        self
+    }
+
+    /// Makes the number of frames variable.
+    #[cfg(not(feature = "gte-vapoursynth-api-32"))]
+    #[inline]
+    pub fn variable_num_frames(mut self) -> Self {
+        self.info.num_frames = Property::Variable;
+        self
+    }
+
+    /// Sets the flags.
+    #[inline]
+    pub fn flags(mut self, flags: node::Flags) -> Self {
+        self.info.flags = flags;
+        self
+    }
+
+    /// Validates the parameters and returns the `VideoInfo`.
+    pub fn build(self) -> Result<VideoInfo<'core>, VideoInfoError> {
+        let info = self.info;
+
+        if let Property::Constant(resolution) = info.resolution {
+            if resolution.width == 0 || resolution.height == 0 {
+                return Err(VideoInfoError::ZeroResolution);
+            }
+
+            if let Property::Constant(format) = info.format {
+                let mask_w = (1 << format.sub_sampling_w()) - 1;
+                let mask_h = (1 << format.sub_sampling_h()) - 1;
+                if resolution.width & mask_w != 0 || resolution.height & mask_h != 0 {
+                    return Err(VideoInfoError::IncompatibleSubSampling);
+                }
+            }
+        }
+
+        if let Property::Constant(framerate) = info.framerate {
+            if framerate.numerator == 0 || framerate.denominator == 0 {
+                return Err(VideoInfoError::ZeroFramerate);
+            }
+
+            if !framerate.is_reduced() {
+                return Err(VideoInfoError::FramerateNotReduced(framerate));
+            }
+        }
+
+        if info.constant_num_frames() == Some(0) {
+            return Err(VideoInfoError::ZeroLength);
+        }
+
+        Ok(info)
+    }
+}
+
+impl<'core> Default for VideoInfoBuilder<'core> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'core> From<VideoInfo<'core>> for VideoInfoBuilder<'core> {
+    #[inline]
+    fn from(info: VideoInfo<'core>) -> Self {
+        Self { info }
+    }
+}
+
 impl<'core> VideoInfo<'core> {
+    /// Returns a builder seeded with this `VideoInfo`.
+    #[inline]
+    pub fn to_builder(&self) -> VideoInfoBuilder<'core> {
+        VideoInfoBuilder::from(*self)
+    }
+
+    /// Returns the number of frames if it's known.
+    #[inline]
+    pub(crate) fn constant_num_frames(&self) -> Option<usize> {
+        #[cfg(feature = "gte-vapoursynth-api-32")]
+        {
+            Some(self.num_frames)
+        }
+        #[cfg(not(feature = "gte-vapoursynth-api-32"))]
+        {
+            match self.num_frames {
+                Property::Variable => None,
+                Property::Constant(x) => Some(x),
+            }
+        }
+    }
+
+    /// Returns the duration of the clip.
+    ///
+    /// Returns `None` if the framerate or the number of frames is variable.
+    #[inline]
+    pub fn duration(&self) -> Option<Duration> {
+        let num_frames = self.constant_num_frames()?;
+        self.frame_time(num_frames)
+    }
+
+    /// Returns the timestamp of frame `n`.
+    ///
+    /// Returns `None` if the framerate is variable.
+    #[inline]
+    pub fn frame_time(&self, n: usize) -> Option<Duration> {
+        match self.framerate {
+            Property::Variable => None,
+            Property::Constant(framerate) => Some(framerate.frame_time(n)),
+        }
+    }
+
+    /// Returns the number of the frame displayed at time `t`.
+    ///
+    /// Returns `None` if the framerate is variable or if `t` is past the end of the clip.
+    #[inline]
+    pub fn frame_at_time(&self, t: Duration) -> Option<usize> {
+        let n = match self.framerate {
+            Property::Variable => return None,
+            Property::Constant(framerate) => framerate.frame_at_time(t),
+        };
+
+        match self.constant_num_frames() {
+            Some(num_frames) if n >= num_frames => None,
+            _ => Some(n),
+        }
+    }
+
     /// Creates a `VideoInfo` from a raw pointer.
     ///
     /// # Safety
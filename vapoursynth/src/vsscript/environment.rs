@@ -75,6 +75,9 @@ impl Environment {
     /// Useful if it is necessary to set some variable in the script environment before evaluating
     /// any scripts.
     pub fn new() -> Result<Self> {
+        #[cfg(feature = "runtime-loading")]
+        super::load_library(None).map_err(Error::Library)?;
+
         maybe_initialize();
 
         let mut handle = ptr::null_mut();
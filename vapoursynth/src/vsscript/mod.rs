@@ -1,10 +1,14 @@
 //! VapourSynth script-related things.
 
+use std::path::Path;
 #[cfg(not(feature = "gte-vsscript-api-32"))]
 use std::sync::Mutex;
 use std::sync::Once;
 use vapoursynth_sys as ffi;
 
+#[cfg(feature = "runtime-loading")]
+use api::LoadError;
+
 #[cfg(not(feature = "gte-vsscript-api-32"))]
 lazy_static! {
     static ref FFI_CALL_MUTEX: Mutex<()> = Mutex::new(());
@@ -34,11 +38,12 @@ pub(crate) fn maybe_initialize() {
     static ONCE: Once = Once::new();
 
     ONCE.call_once(|| unsafe {
-        ffi::vsscript_init();
+        // Zero indicates failure, for example when the library couldn't be loaded at runtime.
+        let initialized = ffi::vsscript_init() != 0;
 
         // Verify the VSScript API version.
         #[cfg(feature = "gte-vsscript-api-31")]
-        {
+        if initialized {
             fn split_version(version: i32) -> (i32, i32) {
                 (version >> 16, version & 0xFFFF)
             }
@@ -62,6 +67,17 @@ pub(crate) fn maybe_initialize() {
     });
 }
 
+/// Loads the VSScript library.
+///
+/// This is only needed to load the library from a custom `path`, otherwise it's loaded from the
+/// default system location on the first use. With `path` equal to `None`, this loads the library
+/// from the default location unless it's already loaded.
+#[cfg(feature = "runtime-loading")]
+#[inline]
+pub fn load_library(path: Option<&Path>) -> Result<(), LoadError> {
+    ffi::load_vsscript(path)
+}
+
 mod errors;
 pub use self::errors::{Error, VSScriptError};
 